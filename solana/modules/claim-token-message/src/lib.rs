@@ -0,0 +1,1256 @@
+//! Wire format of the Wormhole messages sent and received by the claim-token
+//! program, as plain functions over bytes.
+//!
+//! The program encodes and decodes every message through this crate, so
+//! off-chain relayers and indexers depending on it parse messages exactly like
+//! the program does, without pulling in Anchor or the Solana SDK. Disable the
+//! default `std` feature to build it with `no_std` and `alloc` only.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+pub const PAYLOAD_ID_ALIVE: u8 = 0;
+pub const PAYLOAD_ID_HELLO: u8 = 1;
+pub const PAYLOAD_ID_MULTI_USER_INFO: u8 = 2;
+
+/// Layout version written after the payload ID of a
+/// [`Message::MultiUserInfo`] message. Versions other than this one,
+/// [`MULTI_USER_INFO_DELEGATED_VERSION`], [`MULTI_USER_INFO_EXPIRING_VERSION`],
+/// [`MULTI_USER_INFO_CORRELATED_VERSION`] and
+/// [`MULTI_USER_INFO_REFERRED_VERSION`] are rejected when decoding.
+pub const MULTI_USER_INFO_VERSION: u8 = 1;
+
+/// Layout version of a [`Message::MultiUserInfo`] message whose entries are
+/// each followed by the delegate allowed to claim on the user's behalf.
+pub const MULTI_USER_INFO_DELEGATED_VERSION: u8 = 2;
+
+/// Layout version of a [`Message::MultiUserInfo`] message whose entries are
+/// each followed by their delegate and the unix timestamp after which the
+/// allocation can no longer be claimed.
+pub const MULTI_USER_INFO_EXPIRING_VERSION: u8 = 3;
+
+/// Layout version of a [`Message::MultiUserInfo`] message whose entries are
+/// each followed by their delegate, their expiry and a 16-byte id correlating
+/// the allocation with an off-chain request.
+pub const MULTI_USER_INFO_CORRELATED_VERSION: u8 = 4;
+
+/// Layout version of a [`Message::MultiUserInfo`] message whose entries are
+/// each followed by their delegate, expiry, correlation id and the wallet
+/// that referred the user.
+pub const MULTI_USER_INFO_REFERRED_VERSION: u8 = 5;
+
+/// Layout version written after the program ID of a [`Message::Alive`]
+/// message, followed by the u16 length of the fields it appends. Version 0 is
+/// the legacy form, which ends at the program ID. Fields of newer versions are
+/// skipped when decoding.
+pub const ALIVE_VERSION: u8 = 1;
+
+/// Maximum length of a [`Message::Hello`] message. Encoding, decoding and the
+/// program's `receive_message` all use this limit, so senders should never
+/// emit a longer message.
+pub const HELLO_MESSAGE_MAX_LENGTH: usize = 512;
+
+/// Length of an encoded `(user, amount)` entry of a
+/// [`Message::MultiUserInfo`] message.
+pub const ENTRY_LEN: usize = 32 + 8;
+
+/// Maximum number of entries in a [`Message::MultiUserInfo`] message, so that
+/// the version and decimals bytes, the u16 count prefix and the entries fit in
+/// [`HELLO_MESSAGE_MAX_LENGTH`].
+pub const MULTI_USER_INFO_MAX_ENTRIES: usize = (HELLO_MESSAGE_MAX_LENGTH - 4) / ENTRY_LEN;
+
+/// Length of an encoded `(user, amount, delegate)` entry of a
+/// [`MULTI_USER_INFO_DELEGATED_VERSION`] message.
+pub const DELEGATED_ENTRY_LEN: usize = ENTRY_LEN + 32;
+
+/// Maximum number of entries in a [`MULTI_USER_INFO_DELEGATED_VERSION`]
+/// message.
+pub const MULTI_USER_INFO_MAX_DELEGATED_ENTRIES: usize =
+    (HELLO_MESSAGE_MAX_LENGTH - 4) / DELEGATED_ENTRY_LEN;
+
+/// Length of an encoded `(user, amount, delegate, expiry)` entry of a
+/// [`MULTI_USER_INFO_EXPIRING_VERSION`] message.
+pub const EXPIRING_ENTRY_LEN: usize = DELEGATED_ENTRY_LEN + 8;
+
+/// Maximum number of entries in a [`MULTI_USER_INFO_EXPIRING_VERSION`]
+/// message.
+pub const MULTI_USER_INFO_MAX_EXPIRING_ENTRIES: usize =
+    (HELLO_MESSAGE_MAX_LENGTH - 4) / EXPIRING_ENTRY_LEN;
+
+/// Length of an encoded `(user, amount, delegate, expiry, correlation_id)`
+/// entry of a [`MULTI_USER_INFO_CORRELATED_VERSION`] message.
+pub const CORRELATED_ENTRY_LEN: usize = EXPIRING_ENTRY_LEN + 16;
+
+/// Maximum number of entries in a [`MULTI_USER_INFO_CORRELATED_VERSION`]
+/// message.
+pub const MULTI_USER_INFO_MAX_CORRELATED_ENTRIES: usize =
+    (HELLO_MESSAGE_MAX_LENGTH - 4) / CORRELATED_ENTRY_LEN;
+
+/// Length of an encoded `(user, amount, delegate, expiry, correlation_id,
+/// referrer)` entry of a [`MULTI_USER_INFO_REFERRED_VERSION`] message.
+pub const REFERRED_ENTRY_LEN: usize = CORRELATED_ENTRY_LEN + 32;
+
+/// Maximum number of entries in a [`MULTI_USER_INFO_REFERRED_VERSION`]
+/// message.
+pub const MULTI_USER_INFO_MAX_REFERRED_ENTRIES: usize =
+    (HELLO_MESSAGE_MAX_LENGTH - 4) / REFERRED_ENTRY_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Error returned when a [`Message`] can't be encoded or decoded. With the
+/// `std` feature it converts into an [`std::io::Error`] wrapping it, which is
+/// what the program's serialization returns. Retrieve it with
+/// [`std::io::Error::get_ref`] and `downcast_ref`.
+pub enum MessageError {
+    /// A `Hello` message longer than [`HELLO_MESSAGE_MAX_LENGTH`] bytes, or a
+    /// `MultiUserInfo` message with more than [`MULTI_USER_INFO_MAX_ENTRIES`]
+    /// entries.
+    TooLarge { len: usize, max: usize },
+    /// A `MultiUserInfo` message with a layout version other than
+    /// [`MULTI_USER_INFO_VERSION`].
+    UnsupportedVersion { version: u8 },
+    /// A message ending before the `len` bytes of the field being read, such
+    /// as a `Hello` or `MultiUserInfo` body shorter than its length or count
+    /// prefix declares.
+    Truncated { len: usize },
+    /// A `MultiUserInfo` message without entries.
+    Empty,
+    /// A `MultiUserInfo` message with delegates for some of its entries only.
+    DelegateMismatch { entries: usize, delegates: usize },
+    /// A `MultiUserInfo` message with expiries for some of its entries only.
+    ExpiryMismatch { entries: usize, expiries: usize },
+    /// A `MultiUserInfo` message with correlation ids for some of its entries
+    /// only.
+    CorrelationMismatch {
+        entries: usize,
+        correlation_ids: usize,
+    },
+    /// A `MultiUserInfo` message with referrers for some of its entries only.
+    ReferrerMismatch { entries: usize, referrers: usize },
+    /// A payload ID other than the `PAYLOAD_ID_*` constants.
+    InvalidPayloadId { id: u8 },
+    /// Bytes left over after the message, rejected by [`decode_exact`].
+    TrailingBytes,
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::TooLarge { len, max } => write!(f, "length {len} exceeds {max}"),
+            MessageError::UnsupportedVersion { version } => {
+                write!(f, "unsupported version {version}")
+            }
+            MessageError::Truncated { len } => write!(f, "body shorter than {len} bytes"),
+            MessageError::Empty => write!(f, "no entries"),
+            MessageError::DelegateMismatch { entries, delegates } => {
+                write!(f, "{delegates} delegates for {entries} entries")
+            }
+            MessageError::ExpiryMismatch { entries, expiries } => {
+                write!(f, "{expiries} expiries for {entries} entries")
+            }
+            MessageError::CorrelationMismatch {
+                entries,
+                correlation_ids,
+            } => write!(f, "{correlation_ids} correlation ids for {entries} entries"),
+            MessageError::ReferrerMismatch { entries, referrers } => {
+                write!(f, "{referrers} referrers for {entries} entries")
+            }
+            MessageError::InvalidPayloadId { id } => write!(f, "invalid payload ID {id}"),
+            MessageError::TrailingBytes => write!(f, "bytes left over after the message"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MessageError {}
+
+#[cfg(feature = "std")]
+impl From<MessageError> for std::io::Error {
+    fn from(err: MessageError) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            MessageError::TooLarge { .. }
+            | MessageError::DelegateMismatch { .. }
+            | MessageError::ExpiryMismatch { .. }
+            | MessageError::CorrelationMismatch { .. }
+            | MessageError::ReferrerMismatch { .. }
+            | MessageError::InvalidPayloadId { .. } => ErrorKind::InvalidInput,
+            MessageError::UnsupportedVersion { .. }
+            | MessageError::Empty
+            | MessageError::TrailingBytes => ErrorKind::InvalidData,
+            MessageError::Truncated { .. } => ErrorKind::UnexpectedEof,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Decoded message. Pubkeys are kept as their 32 bytes.
+pub enum Message {
+    Alive {
+        version: u8,
+        program_id: [u8; 32],
+    },
+    Hello {
+        message: Vec<u8>,
+    },
+    MultiUserInfo {
+        source_decimals: u8,
+        entries: Vec<([u8; 32], u64)>,
+        /// Delegate of every entry, in order, in a
+        /// [`MULTI_USER_INFO_DELEGATED_VERSION`] message. Empty in a
+        /// [`MULTI_USER_INFO_VERSION`] message. All zeros names no delegate.
+        delegates: Vec<[u8; 32]>,
+        /// Expiry of every entry, in order, as a unix timestamp in a
+        /// [`MULTI_USER_INFO_EXPIRING_VERSION`] message, which also names
+        /// every delegate. Empty otherwise. Zero sets no expiry.
+        expiries: Vec<i64>,
+        /// Correlation id of every entry, in order, in a
+        /// [`MULTI_USER_INFO_CORRELATED_VERSION`] message, which also names
+        /// every delegate and expiry. Empty otherwise. All zeros sets no id.
+        correlation_ids: Vec<[u8; 16]>,
+        /// Wallet that referred every entry's user, in order, in a
+        /// [`MULTI_USER_INFO_REFERRED_VERSION`] message, which also names
+        /// every delegate, expiry and correlation id. Empty otherwise. All
+        /// zeros names no referrer.
+        referrers: Vec<[u8; 32]>,
+    },
+}
+
+/// Source of the bytes a [`Message`] is decoded from.
+pub trait Read {
+    /// Reads up to `buf.len()` bytes into `buf`, returning how many were read.
+    /// Reads fewer only once the source is exhausted.
+    fn read(&mut self, buf: &mut [u8]) -> usize;
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len());
+        let (read, rest) = self.split_at(len);
+        buf[..len].copy_from_slice(read);
+        *self = rest;
+        len
+    }
+}
+
+/// Appends an `Alive` message to `out`. Version 0 writes the legacy form.
+pub fn encode_alive(version: u8, program_id: &[u8; 32], out: &mut Vec<u8>) {
+    out.push(PAYLOAD_ID_ALIVE);
+    out.extend_from_slice(program_id);
+    if version != 0 {
+        out.push(version);
+        // No fields are appended yet.
+        out.extend_from_slice(&0u16.to_be_bytes());
+    }
+}
+
+/// Appends a `Hello` message to `out`.
+pub fn encode_hello(message: &[u8], out: &mut Vec<u8>) -> Result<(), MessageError> {
+    if message.len() > HELLO_MESSAGE_MAX_LENGTH {
+        return Err(MessageError::TooLarge {
+            len: message.len(),
+            max: HELLO_MESSAGE_MAX_LENGTH,
+        });
+    }
+    out.push(PAYLOAD_ID_HELLO);
+    out.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    out.extend_from_slice(message);
+    Ok(())
+}
+
+/// Appends a `MultiUserInfo` message to `out`. Nothing is appended on error.
+pub fn encode_multi_user_info(
+    source_decimals: u8,
+    entries: impl ExactSizeIterator<Item = ([u8; 32], u64)>,
+    out: &mut Vec<u8>,
+) -> Result<(), MessageError> {
+    encode_entries(
+        MULTI_USER_INFO_VERSION,
+        MULTI_USER_INFO_MAX_ENTRIES,
+        source_decimals,
+        entries,
+        out,
+        |(user, amount), out| {
+            out.extend_from_slice(&user);
+            out.extend_from_slice(&amount.to_be_bytes());
+        },
+    )
+}
+
+/// Appends a [`MULTI_USER_INFO_DELEGATED_VERSION`] `MultiUserInfo` message of
+/// `(user, amount, delegate)` entries to `out`. Nothing is appended on error.
+pub fn encode_delegated_multi_user_info(
+    source_decimals: u8,
+    entries: impl ExactSizeIterator<Item = ([u8; 32], u64, [u8; 32])>,
+    out: &mut Vec<u8>,
+) -> Result<(), MessageError> {
+    encode_entries(
+        MULTI_USER_INFO_DELEGATED_VERSION,
+        MULTI_USER_INFO_MAX_DELEGATED_ENTRIES,
+        source_decimals,
+        entries,
+        out,
+        |(user, amount, delegate), out| {
+            out.extend_from_slice(&user);
+            out.extend_from_slice(&amount.to_be_bytes());
+            out.extend_from_slice(&delegate);
+        },
+    )
+}
+
+/// Appends a [`MULTI_USER_INFO_EXPIRING_VERSION`] `MultiUserInfo` message of
+/// `(user, amount, delegate, expiry)` entries to `out`. Nothing is appended on
+/// error.
+pub fn encode_expiring_multi_user_info(
+    source_decimals: u8,
+    entries: impl ExactSizeIterator<Item = ([u8; 32], u64, [u8; 32], i64)>,
+    out: &mut Vec<u8>,
+) -> Result<(), MessageError> {
+    encode_entries(
+        MULTI_USER_INFO_EXPIRING_VERSION,
+        MULTI_USER_INFO_MAX_EXPIRING_ENTRIES,
+        source_decimals,
+        entries,
+        out,
+        |(user, amount, delegate, expiry), out| {
+            out.extend_from_slice(&user);
+            out.extend_from_slice(&amount.to_be_bytes());
+            out.extend_from_slice(&delegate);
+            out.extend_from_slice(&expiry.to_be_bytes());
+        },
+    )
+}
+
+/// Appends a [`MULTI_USER_INFO_CORRELATED_VERSION`] `MultiUserInfo` message of
+/// `(user, amount, delegate, expiry, correlation_id)` entries to `out`.
+/// Nothing is appended on error.
+pub fn encode_correlated_multi_user_info(
+    source_decimals: u8,
+    entries: impl ExactSizeIterator<Item = ([u8; 32], u64, [u8; 32], i64, [u8; 16])>,
+    out: &mut Vec<u8>,
+) -> Result<(), MessageError> {
+    encode_entries(
+        MULTI_USER_INFO_CORRELATED_VERSION,
+        MULTI_USER_INFO_MAX_CORRELATED_ENTRIES,
+        source_decimals,
+        entries,
+        out,
+        |(user, amount, delegate, expiry, correlation_id), out| {
+            out.extend_from_slice(&user);
+            out.extend_from_slice(&amount.to_be_bytes());
+            out.extend_from_slice(&delegate);
+            out.extend_from_slice(&expiry.to_be_bytes());
+            out.extend_from_slice(&correlation_id);
+        },
+    )
+}
+
+/// Appends a [`MULTI_USER_INFO_REFERRED_VERSION`] `MultiUserInfo` message of
+/// `(user, amount, delegate, expiry, correlation_id, referrer)` entries to
+/// `out`. Nothing is appended on error.
+pub fn encode_referred_multi_user_info(
+    source_decimals: u8,
+    entries: impl ExactSizeIterator<Item = ([u8; 32], u64, [u8; 32], i64, [u8; 16], [u8; 32])>,
+    out: &mut Vec<u8>,
+) -> Result<(), MessageError> {
+    encode_entries(
+        MULTI_USER_INFO_REFERRED_VERSION,
+        MULTI_USER_INFO_MAX_REFERRED_ENTRIES,
+        source_decimals,
+        entries,
+        out,
+        |(user, amount, delegate, expiry, correlation_id, referrer), out| {
+            out.extend_from_slice(&user);
+            out.extend_from_slice(&amount.to_be_bytes());
+            out.extend_from_slice(&delegate);
+            out.extend_from_slice(&expiry.to_be_bytes());
+            out.extend_from_slice(&correlation_id);
+            out.extend_from_slice(&referrer);
+        },
+    )
+}
+
+/// Appends the header of a `MultiUserInfo` message with layout `version`
+/// followed by `entries`, each written by `encode_entry`.
+fn encode_entries<E>(
+    version: u8,
+    max: usize,
+    source_decimals: u8,
+    entries: impl ExactSizeIterator<Item = E>,
+    out: &mut Vec<u8>,
+    encode_entry: impl Fn(E, &mut Vec<u8>),
+) -> Result<(), MessageError> {
+    if entries.len() == 0 {
+        return Err(MessageError::Empty);
+    }
+    if entries.len() > max {
+        return Err(MessageError::TooLarge {
+            len: entries.len(),
+            max,
+        });
+    }
+    out.extend_from_slice(&[PAYLOAD_ID_MULTI_USER_INFO, version, source_decimals]);
+    out.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for entry in entries {
+        encode_entry(entry, out);
+    }
+    Ok(())
+}
+
+impl Message {
+    /// Appends this message to `out`.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), MessageError> {
+        match self {
+            Message::Alive {
+                version,
+                program_id,
+            } => {
+                encode_alive(*version, program_id, out);
+                Ok(())
+            }
+            Message::Hello { message } => encode_hello(message, out),
+            Message::MultiUserInfo {
+                source_decimals,
+                entries,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            } if delegates.is_empty()
+                && expiries.is_empty()
+                && correlation_ids.is_empty()
+                && referrers.is_empty() =>
+            {
+                encode_multi_user_info(*source_decimals, entries.iter().copied(), out)
+            }
+            Message::MultiUserInfo {
+                source_decimals,
+                entries,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            } => {
+                if delegates.len() != entries.len() {
+                    return Err(MessageError::DelegateMismatch {
+                        entries: entries.len(),
+                        delegates: delegates.len(),
+                    });
+                }
+                if expiries.is_empty() && correlation_ids.is_empty() && referrers.is_empty() {
+                    return encode_delegated_multi_user_info(
+                        *source_decimals,
+                        entries
+                            .iter()
+                            .zip(delegates)
+                            .map(|((user, amount), delegate)| (*user, *amount, *delegate)),
+                        out,
+                    );
+                }
+                if expiries.len() != entries.len() {
+                    return Err(MessageError::ExpiryMismatch {
+                        entries: entries.len(),
+                        expiries: expiries.len(),
+                    });
+                }
+                if correlation_ids.is_empty() && referrers.is_empty() {
+                    return encode_expiring_multi_user_info(
+                        *source_decimals,
+                        entries.iter().zip(delegates).zip(expiries).map(
+                            |(((user, amount), delegate), expiry)| {
+                                (*user, *amount, *delegate, *expiry)
+                            },
+                        ),
+                        out,
+                    );
+                }
+                if correlation_ids.len() != entries.len() {
+                    return Err(MessageError::CorrelationMismatch {
+                        entries: entries.len(),
+                        correlation_ids: correlation_ids.len(),
+                    });
+                }
+                if referrers.is_empty() {
+                    return encode_correlated_multi_user_info(
+                        *source_decimals,
+                        entries
+                            .iter()
+                            .zip(delegates)
+                            .zip(expiries)
+                            .zip(correlation_ids)
+                            .map(|((((user, amount), delegate), expiry), correlation_id)| {
+                                (*user, *amount, *delegate, *expiry, *correlation_id)
+                            }),
+                        out,
+                    );
+                }
+                if referrers.len() != entries.len() {
+                    return Err(MessageError::ReferrerMismatch {
+                        entries: entries.len(),
+                        referrers: referrers.len(),
+                    });
+                }
+                encode_referred_multi_user_info(
+                    *source_decimals,
+                    entries
+                        .iter()
+                        .zip(delegates)
+                        .zip(expiries)
+                        .zip(correlation_ids)
+                        .zip(referrers)
+                        .map(
+                            |(((((user, amount), delegate), expiry), correlation_id), referrer)| {
+                                (
+                                    *user,
+                                    *amount,
+                                    *delegate,
+                                    *expiry,
+                                    *correlation_id,
+                                    *referrer,
+                                )
+                            },
+                        ),
+                    out,
+                )
+            }
+        }
+    }
+
+    /// Number of users funded by this message.
+    pub fn user_count(&self) -> usize {
+        match self {
+            Message::MultiUserInfo { entries, .. } => entries.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Decodes the message at the start of `data`. Bytes past its end are ignored.
+pub fn decode(mut data: &[u8]) -> Result<Message, MessageError> {
+    decode_from(&mut data)
+}
+
+/// Decodes the message filling all of `data`, failing with
+/// [`MessageError::TrailingBytes`] if bytes are left over.
+pub fn decode_exact(mut data: &[u8]) -> Result<Message, MessageError> {
+    decode_exact_from(&mut data)
+}
+
+/// Decodes a message from `reader` like [`decode_exact`], failing if the
+/// reader isn't exhausted at the end of the message.
+pub fn decode_exact_from<R: Read>(reader: &mut R) -> Result<Message, MessageError> {
+    let msg = decode_from(reader)?;
+    if reader.read(&mut [0u8; 1]) != 0 {
+        return Err(MessageError::TrailingBytes);
+    }
+    Ok(msg)
+}
+
+/// Decodes a message from `reader`, reading no further than its end.
+pub fn decode_from<R: Read>(reader: &mut R) -> Result<Message, MessageError> {
+    match read_u8(reader)? {
+        PAYLOAD_ID_ALIVE => {
+            let mut program_id = [0u8; 32];
+            read_exact(reader, &mut program_id)?;
+            // Legacy messages end at the program ID.
+            let mut version = [0u8; 1];
+            if reader.read(&mut version) == 1 && version[0] != 0 {
+                let length = read_u16(reader)?;
+                skip(reader, length.into())?;
+            }
+            Ok(Message::Alive {
+                version: version[0],
+                program_id,
+            })
+        }
+        PAYLOAD_ID_HELLO => {
+            let length = read_u16(reader)?.into();
+            if length > HELLO_MESSAGE_MAX_LENGTH {
+                return Err(MessageError::TooLarge {
+                    len: length,
+                    max: HELLO_MESSAGE_MAX_LENGTH,
+                });
+            }
+            let mut message = vec![0; length];
+            read_exact(reader, &mut message)?;
+            Ok(Message::Hello { message })
+        }
+        PAYLOAD_ID_MULTI_USER_INFO => {
+            let version = read_u8(reader)?;
+            let (entry_len, max) = match version {
+                MULTI_USER_INFO_VERSION => (ENTRY_LEN, MULTI_USER_INFO_MAX_ENTRIES),
+                MULTI_USER_INFO_DELEGATED_VERSION => {
+                    (DELEGATED_ENTRY_LEN, MULTI_USER_INFO_MAX_DELEGATED_ENTRIES)
+                }
+                MULTI_USER_INFO_EXPIRING_VERSION => {
+                    (EXPIRING_ENTRY_LEN, MULTI_USER_INFO_MAX_EXPIRING_ENTRIES)
+                }
+                MULTI_USER_INFO_CORRELATED_VERSION => {
+                    (CORRELATED_ENTRY_LEN, MULTI_USER_INFO_MAX_CORRELATED_ENTRIES)
+                }
+                MULTI_USER_INFO_REFERRED_VERSION => {
+                    (REFERRED_ENTRY_LEN, MULTI_USER_INFO_MAX_REFERRED_ENTRIES)
+                }
+                _ => return Err(MessageError::UnsupportedVersion { version }),
+            };
+            let source_decimals = read_u8(reader)?;
+            let count = read_u16(reader)?.into();
+            if count == 0 {
+                return Err(MessageError::Empty);
+            }
+            if count > max {
+                return Err(MessageError::TooLarge { len: count, max });
+            }
+            let mut body = vec![0; count * entry_len];
+            read_exact(reader, &mut body)?;
+            let entries = body
+                .chunks_exact(entry_len)
+                .map(|entry| {
+                    (
+                        entry[..32].try_into().unwrap(),
+                        u64::from_be_bytes(entry[32..ENTRY_LEN].try_into().unwrap()),
+                    )
+                })
+                .collect();
+            let delegates = if version == MULTI_USER_INFO_VERSION {
+                Vec::new()
+            } else {
+                body.chunks_exact(entry_len)
+                    .map(|entry| entry[ENTRY_LEN..DELEGATED_ENTRY_LEN].try_into().unwrap())
+                    .collect()
+            };
+            let expiries = if version >= MULTI_USER_INFO_EXPIRING_VERSION {
+                body.chunks_exact(entry_len)
+                    .map(|entry| {
+                        i64::from_be_bytes(
+                            entry[DELEGATED_ENTRY_LEN..EXPIRING_ENTRY_LEN]
+                                .try_into()
+                                .unwrap(),
+                        )
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let correlation_ids = if version >= MULTI_USER_INFO_CORRELATED_VERSION {
+                body.chunks_exact(entry_len)
+                    .map(|entry| {
+                        entry[EXPIRING_ENTRY_LEN..CORRELATED_ENTRY_LEN]
+                            .try_into()
+                            .unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let referrers = if version == MULTI_USER_INFO_REFERRED_VERSION {
+                body.chunks_exact(entry_len)
+                    .map(|entry| entry[CORRELATED_ENTRY_LEN..].try_into().unwrap())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            Ok(Message::MultiUserInfo {
+                source_decimals,
+                entries,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            })
+        }
+        id => Err(MessageError::InvalidPayloadId { id }),
+    }
+}
+
+/// Fills `buf` from `reader`, failing with [`MessageError::Truncated`] if the
+/// reader runs out first.
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), MessageError> {
+    if reader.read(buf) < buf.len() {
+        return Err(MessageError::Truncated { len: buf.len() });
+    }
+    Ok(())
+}
+
+/// Consumes `len` bytes from `reader` through a small buffer, so skipping a
+/// large declared length doesn't allocate it. Fails with
+/// [`MessageError::Truncated`] if the reader runs out first.
+fn skip<R: Read>(reader: &mut R, len: usize) -> Result<(), MessageError> {
+    let mut buf = [0u8; 64];
+    let mut left = len;
+    while left > 0 {
+        let chunk = left.min(buf.len());
+        if reader.read(&mut buf[..chunk]) < chunk {
+            return Err(MessageError::Truncated { len });
+        }
+        left -= chunk;
+    }
+    Ok(())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, MessageError> {
+    let mut buf = [0u8; 1];
+    read_exact(reader, &mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, MessageError> {
+    let mut buf = [0u8; 2];
+    read_exact(reader, &mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn round_trip(msg: &Message) -> Result<Vec<u8>, MessageError> {
+        let mut encoded = Vec::new();
+        msg.encode(&mut encoded)?;
+        assert_eq!(decode(&encoded)?, *msg);
+        Ok(encoded)
+    }
+
+    #[test]
+    fn test_alive() -> Result<(), MessageError> {
+        let program_id = [7u8; 32];
+        let encoded = round_trip(&Message::Alive {
+            version: ALIVE_VERSION,
+            program_id,
+        })?;
+        assert_eq!(encoded.len(), 1 + 32 + 1 + 2);
+        assert_eq!(encoded[0], PAYLOAD_ID_ALIVE);
+        assert_eq!(encoded[1..33], program_id);
+        assert_eq!(encoded[33..], [ALIVE_VERSION, 0, 0]);
+
+        // The legacy form ends at the program ID.
+        let encoded = round_trip(&Message::Alive {
+            version: 0,
+            program_id,
+        })?;
+        assert_eq!(encoded.len(), 1 + 32);
+
+        // Fields appended by a newer version are skipped.
+        let mut encoded = vec![PAYLOAD_ID_ALIVE];
+        encoded.extend_from_slice(&program_id);
+        encoded.extend_from_slice(&[ALIVE_VERSION + 1, 0, 8]);
+        encoded.extend_from_slice(&1_700_000_000i64.to_be_bytes());
+        let mut reader = encoded.as_slice();
+        assert_eq!(
+            decode_from(&mut reader)?,
+            Message::Alive {
+                version: ALIVE_VERSION + 1,
+                program_id,
+            }
+        );
+        assert!(reader.is_empty(), "appended fields not consumed");
+
+        // The largest declared length is skipped in full, and reported when
+        // the message is shorter.
+        let mut encoded = vec![PAYLOAD_ID_ALIVE];
+        encoded.extend_from_slice(&program_id);
+        encoded.extend_from_slice(&[ALIVE_VERSION + 1]);
+        encoded.extend_from_slice(&u16::MAX.to_be_bytes());
+        encoded.resize(encoded.len() + usize::from(u16::MAX), 1);
+        let mut reader = encoded.as_slice();
+        assert_eq!(
+            decode_from(&mut reader)?,
+            Message::Alive {
+                version: ALIVE_VERSION + 1,
+                program_id,
+            }
+        );
+        assert!(reader.is_empty(), "appended fields not consumed");
+        encoded.pop();
+        assert_eq!(
+            decode(&encoded),
+            Err(MessageError::Truncated {
+                len: u16::MAX.into()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hello() -> Result<(), MessageError> {
+        let message = b"All your base are belong to us".to_vec();
+        let encoded = round_trip(&Message::Hello {
+            message: message.clone(),
+        })?;
+        assert_eq!(encoded[0], PAYLOAD_ID_HELLO);
+        assert_eq!(encoded[1..3], (message.len() as u16).to_be_bytes());
+        assert_eq!(encoded[3..], message);
+
+        let too_large = Message::Hello {
+            message: vec![33u8; HELLO_MESSAGE_MAX_LENGTH + 1],
+        };
+        let mut encoded = Vec::new();
+        let err = MessageError::TooLarge {
+            len: HELLO_MESSAGE_MAX_LENGTH + 1,
+            max: HELLO_MESSAGE_MAX_LENGTH,
+        };
+        assert_eq!(too_large.encode(&mut encoded), Err(err));
+        assert!(encoded.is_empty(), "rejected message encoded");
+
+        encoded.push(PAYLOAD_ID_HELLO);
+        encoded.extend_from_slice(&((HELLO_MESSAGE_MAX_LENGTH + 1) as u16).to_be_bytes());
+        assert_eq!(decode(&encoded), Err(err));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_user_info() -> Result<(), MessageError> {
+        let entries = vec![([1u8; 32], 10_000), ([2u8; 32], 0)];
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        assert_eq!(msg.user_count(), 2);
+        let encoded = round_trip(&msg)?;
+        assert_eq!(encoded.len(), 5 + entries.len() * ENTRY_LEN);
+        assert_eq!(
+            encoded[..5],
+            [PAYLOAD_ID_MULTI_USER_INFO, MULTI_USER_INFO_VERSION, 8, 0, 2]
+        );
+        assert_eq!(encoded[5..37], entries[0].0);
+        assert_eq!(encoded[37..45], 10_000u64.to_be_bytes());
+
+        // The largest message fits in the maximum length.
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![([1u8; 32], 1); MULTI_USER_INFO_MAX_ENTRIES],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        })?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        let mut encoded = Vec::new();
+        assert_eq!(
+            encode_multi_user_info(8, [].into_iter(), &mut encoded),
+            Err(MessageError::Empty)
+        );
+        assert!(encoded.is_empty(), "rejected message encoded");
+        assert_eq!(
+            decode(&[PAYLOAD_ID_MULTI_USER_INFO, MULTI_USER_INFO_VERSION, 8, 0, 0]),
+            Err(MessageError::Empty)
+        );
+        for version in [0, MULTI_USER_INFO_REFERRED_VERSION + 1] {
+            assert_eq!(
+                decode(&[PAYLOAD_ID_MULTI_USER_INFO, version, 8, 0, 1]),
+                Err(MessageError::UnsupportedVersion { version })
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_user_info_delegated() -> Result<(), MessageError> {
+        let entries = vec![([1u8; 32], 10_000), ([2u8; 32], 0)];
+        let delegates = vec![[3u8; 32], [0u8; 32]];
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        })?;
+        assert_eq!(encoded.len(), 5 + entries.len() * DELEGATED_ENTRY_LEN);
+        assert_eq!(
+            encoded[..5],
+            [
+                PAYLOAD_ID_MULTI_USER_INFO,
+                MULTI_USER_INFO_DELEGATED_VERSION,
+                8,
+                0,
+                2
+            ]
+        );
+        assert_eq!(encoded[5..37], entries[0].0);
+        assert_eq!(encoded[37..45], 10_000u64.to_be_bytes());
+        assert_eq!(encoded[45..77], delegates[0]);
+
+        // The largest message fits in the maximum length.
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![([1u8; 32], 1); MULTI_USER_INFO_MAX_DELEGATED_ENTRIES],
+            delegates: vec![[3u8; 32]; MULTI_USER_INFO_MAX_DELEGATED_ENTRIES],
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        })?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        // Every entry needs a delegate.
+        let mut encoded = Vec::new();
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries,
+            delegates: vec![[3u8; 32]],
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::DelegateMismatch {
+                entries: 2,
+                delegates: 1
+            })
+        );
+        assert!(encoded.is_empty(), "rejected message encoded");
+
+        // More entries than fit with delegates are rejected.
+        let mut encoded = vec![
+            PAYLOAD_ID_MULTI_USER_INFO,
+            MULTI_USER_INFO_DELEGATED_VERSION,
+            8,
+        ];
+        encoded
+            .extend_from_slice(&(MULTI_USER_INFO_MAX_DELEGATED_ENTRIES as u16 + 1).to_be_bytes());
+        assert_eq!(
+            decode(&encoded),
+            Err(MessageError::TooLarge {
+                len: MULTI_USER_INFO_MAX_DELEGATED_ENTRIES + 1,
+                max: MULTI_USER_INFO_MAX_DELEGATED_ENTRIES
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_user_info_expiring() -> Result<(), MessageError> {
+        let entries = vec![([1u8; 32], 10_000), ([2u8; 32], 0)];
+        let delegates = vec![[3u8; 32], [0u8; 32]];
+        let expiries = vec![1_700_000_000, 0];
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: expiries.clone(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        })?;
+        assert_eq!(encoded.len(), 5 + entries.len() * EXPIRING_ENTRY_LEN);
+        assert_eq!(
+            encoded[..5],
+            [
+                PAYLOAD_ID_MULTI_USER_INFO,
+                MULTI_USER_INFO_EXPIRING_VERSION,
+                8,
+                0,
+                2
+            ]
+        );
+        assert_eq!(encoded[5..37], entries[0].0);
+        assert_eq!(encoded[37..45], 10_000u64.to_be_bytes());
+        assert_eq!(encoded[45..77], delegates[0]);
+        assert_eq!(encoded[77..85], 1_700_000_000i64.to_be_bytes());
+
+        // The largest message fits in the maximum length.
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![([1u8; 32], 1); MULTI_USER_INFO_MAX_EXPIRING_ENTRIES],
+            delegates: vec![[3u8; 32]; MULTI_USER_INFO_MAX_EXPIRING_ENTRIES],
+            expiries: vec![1; MULTI_USER_INFO_MAX_EXPIRING_ENTRIES],
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        })?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        // Every entry needs an expiry, and a delegate to go with it.
+        let mut encoded = Vec::new();
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: vec![1_700_000_000],
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::ExpiryMismatch {
+                entries: 2,
+                expiries: 1
+            })
+        );
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries,
+            delegates: Vec::new(),
+            expiries,
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::DelegateMismatch {
+                entries: 2,
+                delegates: 0
+            })
+        );
+        assert!(encoded.is_empty(), "rejected message encoded");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_user_info_correlated() -> Result<(), MessageError> {
+        let entries = vec![([1u8; 32], 10_000), ([2u8; 32], 0)];
+        let delegates = vec![[3u8; 32], [0u8; 32]];
+        let expiries = vec![1_700_000_000, 0];
+        let correlation_ids = vec![[4u8; 16], [0u8; 16]];
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: expiries.clone(),
+            correlation_ids: correlation_ids.clone(),
+            referrers: Vec::new(),
+        })?;
+        assert_eq!(encoded.len(), 5 + entries.len() * CORRELATED_ENTRY_LEN);
+        assert_eq!(
+            encoded[..5],
+            [
+                PAYLOAD_ID_MULTI_USER_INFO,
+                MULTI_USER_INFO_CORRELATED_VERSION,
+                8,
+                0,
+                2
+            ]
+        );
+        assert_eq!(encoded[5..37], entries[0].0);
+        assert_eq!(encoded[45..77], delegates[0]);
+        assert_eq!(encoded[77..85], 1_700_000_000i64.to_be_bytes());
+        assert_eq!(encoded[85..101], correlation_ids[0]);
+
+        // The largest message fits in the maximum length.
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![([1u8; 32], 1); MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            delegates: vec![[3u8; 32]; MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            expiries: vec![1; MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            correlation_ids: vec![[4u8; 16]; MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            referrers: Vec::new(),
+        })?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        // Every entry needs a correlation id, and an expiry to go with it.
+        let mut encoded = Vec::new();
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries,
+            correlation_ids: vec![[4u8; 16]],
+            referrers: Vec::new(),
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::CorrelationMismatch {
+                entries: 2,
+                correlation_ids: 1
+            })
+        );
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries,
+            delegates,
+            expiries: Vec::new(),
+            correlation_ids,
+            referrers: Vec::new(),
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::ExpiryMismatch {
+                entries: 2,
+                expiries: 0
+            })
+        );
+        assert!(encoded.is_empty(), "rejected message encoded");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_user_info_referred() -> Result<(), MessageError> {
+        let entries = vec![([1u8; 32], 10_000), ([2u8; 32], 0)];
+        let delegates = vec![[3u8; 32], [0u8; 32]];
+        let expiries = vec![1_700_000_000, 0];
+        let correlation_ids = vec![[4u8; 16], [0u8; 16]];
+        let referrers = vec![[5u8; 32], [0u8; 32]];
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: expiries.clone(),
+            correlation_ids: correlation_ids.clone(),
+            referrers: referrers.clone(),
+        })?;
+        assert_eq!(encoded.len(), 5 + entries.len() * REFERRED_ENTRY_LEN);
+        assert_eq!(
+            encoded[..5],
+            [
+                PAYLOAD_ID_MULTI_USER_INFO,
+                MULTI_USER_INFO_REFERRED_VERSION,
+                8,
+                0,
+                2
+            ]
+        );
+        assert_eq!(encoded[85..101], correlation_ids[0]);
+        assert_eq!(encoded[101..133], referrers[0]);
+
+        // The largest message fits in the maximum length.
+        let encoded = round_trip(&Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![([1u8; 32], 1); MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            delegates: vec![[3u8; 32]; MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            expiries: vec![1; MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            correlation_ids: vec![[4u8; 16]; MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            referrers: vec![[5u8; 32]; MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+        })?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        // Every entry needs a referrer, and a correlation id to go with it.
+        let mut encoded = Vec::new();
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: expiries.clone(),
+            correlation_ids,
+            referrers: vec![[5u8; 32]],
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::ReferrerMismatch {
+                entries: 2,
+                referrers: 1
+            })
+        );
+        let msg = Message::MultiUserInfo {
+            source_decimals: 8,
+            entries,
+            delegates,
+            expiries,
+            correlation_ids: Vec::new(),
+            referrers,
+        };
+        assert_eq!(
+            msg.encode(&mut encoded),
+            Err(MessageError::CorrelationMismatch {
+                entries: 2,
+                correlation_ids: 0
+            })
+        );
+        assert!(encoded.is_empty(), "rejected message encoded");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() -> Result<(), MessageError> {
+        // Headers declaring 40 bytes followed by only 10.
+        let mut hello = vec![PAYLOAD_ID_HELLO, 0, 40];
+        hello.extend_from_slice(&[1u8; 10]);
+        let mut multi_user_info =
+            vec![PAYLOAD_ID_MULTI_USER_INFO, MULTI_USER_INFO_VERSION, 8, 0, 1];
+        multi_user_info.extend_from_slice(&[1u8; 10]);
+        for encoded in [hello, multi_user_info] {
+            assert_eq!(decode(&encoded), Err(MessageError::Truncated { len: 40 }));
+        }
+
+        // Truncated headers.
+        assert_eq!(decode(&[]), Err(MessageError::Truncated { len: 1 }));
+        assert_eq!(
+            decode(&[PAYLOAD_ID_HELLO, 0]),
+            Err(MessageError::Truncated { len: 2 })
+        );
+
+        assert_eq!(decode(&[3]), Err(MessageError::InvalidPayloadId { id: 3 }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_exact() -> Result<(), MessageError> {
+        let messages = [
+            Message::Alive {
+                version: ALIVE_VERSION,
+                program_id: [7u8; 32],
+            },
+            Message::Hello {
+                message: b"All your base are belong to us".to_vec(),
+            },
+            Message::MultiUserInfo {
+                source_decimals: 8,
+                entries: vec![([1u8; 32], 10_000)],
+                delegates: Vec::new(),
+                expiries: Vec::new(),
+                correlation_ids: Vec::new(),
+                referrers: Vec::new(),
+            },
+        ];
+        for msg in messages {
+            let mut encoded = Vec::new();
+            msg.encode(&mut encoded)?;
+            assert_eq!(decode_exact(&encoded)?, msg);
+
+            // Garbage past the end is ignored by decode only.
+            encoded.push(0);
+            assert_eq!(decode(&encoded)?, msg);
+            assert_eq!(decode_exact(&encoded), Err(MessageError::TrailingBytes));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_any_length() {
+        // Decoding is total: arbitrary bytes of any length return an error
+        // instead of panicking. A fixed LCG keeps the inputs reproducible.
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next_byte = || {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (seed >> 56) as u8
+        };
+        for len in 0..=64 {
+            for payload_id in [
+                PAYLOAD_ID_ALIVE,
+                PAYLOAD_ID_HELLO,
+                PAYLOAD_ID_MULTI_USER_INFO,
+            ] {
+                let mut data: Vec<u8> = (0..len).map(|_| next_byte()).collect();
+                if let Some(first) = data.first_mut() {
+                    *first = payload_id;
+                }
+                if let Ok(msg) = decode(&data) {
+                    // Whatever decodes encodes back to a prefix of the input,
+                    // except for skipped Alive fields.
+                    let mut encoded = Vec::new();
+                    msg.encode(&mut encoded).unwrap();
+                    if payload_id != PAYLOAD_ID_ALIVE {
+                        assert_eq!(encoded, data[..encoded.len()]);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,24 +1,35 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
 use wormhole_anchor_sdk::wormhole;
 
 use crate::{
     error::HelloWorldError,
     message::HelloWorldMessage,
-    state::{Config, ForeignEmitter, Received, WormholeEmitter},
+    state::{Config, ForeignEmitter, HealthState, Received, RelayerStake, WormholeEmitter},
+    CustomError, State,
 };
 
 /// AKA `b"sent"`.
 pub const SEED_PREFIX_SENT: &[u8; 4] = b"sent";
 
 #[derive(Accounts)]
-/// Context used to initialize program data (i.e. config).
-pub struct Initialize<'info> {
+/// Context used to initialize the program's Wormhole data (i.e. config).
+pub struct InitializeWormhole<'info> {
     #[account(mut)]
-    /// Whoever initializes the config will be the owner of the program. Signer
-    /// for creating the [`Config`] account and posting a Wormhole message
-    /// indicating that the program is alive.
+    /// Owner set in the [`State`] account. Signer for creating the [`Config`]
+    /// account and posting a Wormhole message indicating that the program is
+    /// alive.
     pub owner: Signer<'info>,
 
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. This program requires that the `owner` specified in the
+    /// context equals the pubkey specified in this account. Read-only.
+    pub state: Account<'info, State>,
+
     #[account(
         init,
         payer = owner,
@@ -27,9 +38,8 @@ pub struct Initialize<'info> {
         space = Config::MAXIMUM_SIZE,
 
     )]
-    /// Config account, which saves program data useful for other instructions.
-    /// Also saves the payer of the [`initialize`](crate::initialize) instruction
-    /// as the program's owner.
+    /// Config account, which saves the Wormhole data useful for other
+    /// instructions.
     pub config: Account<'info, Config>,
 
     /// Wormhole program.
@@ -64,7 +74,7 @@ pub struct Initialize<'info> {
         space = WormholeEmitter::MAXIMUM_SIZE
     )]
     /// This program's emitter account. We create this account in the
-    /// [`initialize`](crate::initialize) instruction, but
+    /// [`initialize_wormhole`](crate::initialize_wormhole) instruction, but
     /// [`wormhole::post_message`] only needs it to be read-only.
     pub wormhole_emitter: Account<'info, WormholeEmitter>,
 
@@ -79,7 +89,7 @@ pub struct Initialize<'info> {
     )]
     /// CHECK: Emitter's sequence account. This is not created until the first
     /// message is posted, so it needs to be an [UncheckedAccount] for the
-    /// [`initialize`](crate::initialize) instruction.
+    /// [`initialize_wormhole`](crate::initialize_wormhole) instruction.
     /// [`wormhole::post_message`] requires this account be mutable.
     pub wormhole_sequence: UncheckedAccount<'info>,
 
@@ -110,22 +120,23 @@ pub struct Initialize<'info> {
 #[instruction(chain: u16)]
 pub struct RegisterEmitter<'info> {
     #[account(mut)]
-    /// Owner of the program set in the [`Config`] account. Signer for creating
-    /// the [`ForeignEmitter`] account.
-    pub owner: Signer<'info>,
+    /// Owner or operator set in the [`State`] account. Signer for creating the
+    /// [`ForeignEmitter`] account.
+    pub authority: Signer<'info>,
 
     #[account(
-        has_one = owner @ HelloWorldError::OwnerOnly,
-        seeds = [Config::SEED_PREFIX],
+        constraint = state.is_owner_or_operator(&authority.key()) @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
         bump
     )]
-    /// Config account. This program requires that the `owner` specified in the
-    /// context equals the pubkey specified in this account. Read-only.
-    pub config: Account<'info, Config>,
+    /// State account. This program requires that the `authority` specified in
+    /// the context is the owner or the operator set in this account.
+    /// Read-only.
+    pub state: Account<'info, State>,
 
     #[account(
-        init,
-        payer = owner,
+        init_if_needed,
+        payer = authority,
         seeds = [
             ForeignEmitter::SEED_PREFIX,
             &chain.to_le_bytes()[..]
@@ -143,10 +154,72 @@ pub struct RegisterEmitter<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(vaa_hash: [u8; 32])]
-pub struct ReceiveMessage<'info> {
+#[instruction(chain: u16)]
+pub struct UpdateEmitter<'info> {
+    /// Owner or operator set in the [`State`] account.
+    pub authority: Signer<'info>,
+
+    #[account(
+        constraint = state.is_owner_or_operator(&authority.key()) @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. This program requires that the `authority` specified in
+    /// the context is the owner or the operator set in this account.
+    /// Read-only.
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        seeds = [
+            ForeignEmitter::SEED_PREFIX,
+            &chain.to_le_bytes()[..]
+        ],
+        bump
+    )]
+    /// Foreign Emitter account. Must already be registered for this Wormhole
+    /// chain ID. Its emitter address is overwritten.
+    pub foreign_emitter: Account<'info, ForeignEmitter>,
+}
+
+#[derive(Accounts)]
+#[instruction(chain: u16)]
+pub struct DeregisterEmitter<'info> {
     #[account(mut)]
-    /// Payer will initialize an account that tracks his own message IDs.
+    /// Owner or operator set in the [`State`] account. Receives the
+    /// [`ForeignEmitter`] account's lamports.
+    pub authority: Signer<'info>,
+
+    #[account(
+        constraint = state.is_owner_or_operator(&authority.key()) @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. This program requires that the `authority` specified in
+    /// the context is the owner or the operator set in this account.
+    /// Read-only.
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [
+            ForeignEmitter::SEED_PREFIX,
+            &chain.to_le_bytes()[..]
+        ],
+        bump
+    )]
+    /// Foreign Emitter account. Closed so that messages from this Wormhole
+    /// chain ID are no longer accepted.
+    pub foreign_emitter: Account<'info, ForeignEmitter>,
+}
+
+#[derive(Accounts)]
+/// Context used to post a Wormhole message indicating that the program is
+/// alive.
+pub struct SendMessageAlive<'info> {
+    #[account(mut)]
+    /// Payer will pay Wormhole fee to post a message.
     pub payer: Signer<'info>,
 
     #[account(
@@ -157,6 +230,69 @@ pub struct ReceiveMessage<'info> {
     /// against the Wormhole accounts in this context. Read-only.
     pub config: Account<'info, Config>,
 
+    /// Wormhole program.
+    pub wormhole_program: Program<'info, wormhole::program::Wormhole>,
+
+    #[account(
+        mut,
+        address = config.wormhole.bridge @ HelloWorldError::InvalidWormholeConfig
+    )]
+    /// Wormhole bridge data. [`wormhole::post_message`] requires this account
+    /// be mutable.
+    pub wormhole_bridge: Account<'info, wormhole::BridgeData>,
+
+    #[account(
+        mut,
+        address = config.wormhole.fee_collector @ HelloWorldError::InvalidWormholeFeeCollector
+    )]
+    /// Wormhole fee collector. [`wormhole::post_message`] requires this
+    /// account be mutable.
+    pub wormhole_fee_collector: Account<'info, wormhole::FeeCollector>,
+
+    #[account(
+        seeds = [WormholeEmitter::SEED_PREFIX],
+        bump,
+    )]
+    /// Program's emitter account. Read-only.
+    pub wormhole_emitter: Account<'info, WormholeEmitter>,
+
+    #[account(
+        mut,
+        address = config.wormhole.sequence @ HelloWorldError::InvalidWormholeSequence
+    )]
+    /// Emitter's sequence account. [`wormhole::post_message`] requires this
+    /// account be mutable.
+    pub wormhole_sequence: Account<'info, wormhole::SequenceTracker>,
+
+    #[account(
+        mut,
+        seeds = [
+            SEED_PREFIX_SENT,
+            &wormhole_sequence.next_value().to_le_bytes()[..]
+        ],
+        bump,
+    )]
+    /// CHECK: Wormhole Message. [`wormhole::post_message`] requires this
+    /// account be mutable.
+    pub wormhole_message: UncheckedAccount<'info>,
+
+    /// System program.
+    pub system_program: Program<'info, System>,
+
+    /// Clock sysvar.
+    pub clock: Sysvar<'info, Clock>,
+
+    /// Rent sysvar.
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(vaa_hash: [u8; 32])]
+pub struct ReceiveMessage<'info> {
+    #[account(mut)]
+    /// Payer will initialize an account that tracks his own message IDs.
+    pub payer: Signer<'info>,
+
     // Wormhole program.
     pub wormhole_program: Program<'info, wormhole::program::Wormhole>,
 
@@ -166,25 +302,43 @@ pub struct ReceiveMessage<'info> {
             &vaa_hash
         ],
         bump,
-        seeds::program = wormhole_program
+        seeds::program = wormhole_program,
+        constraint = posted.data().is_receivable() @ HelloWorldError::InvalidMessage,
+        constraint = state.meets_consistency(posted.finality()) @ HelloWorldError::InsufficientConsistency
     )]
     /// Verified Wormhole message account. The Wormhole program verified
-    /// signatures and posted the account data here. Read-only.
+    /// signatures and posted the account data here. Must carry a payload
+    /// [`receive_message`](crate::receive_message) accepts, emitted at the
+    /// state's `required_consistency` or above. Read-only.
     pub posted: Account<'info, wormhole::PostedVaa<HelloWorldMessage>>,
 
     #[account(
+        mut,
         seeds = [
             ForeignEmitter::SEED_PREFIX,
             &posted.emitter_chain().to_le_bytes()[..]
         ],
         bump,
-        constraint = foreign_emitter.verify(posted.emitter_address()) @ HelloWorldError::InvalidForeignEmitter
+        constraint = foreign_emitter.verify(posted.emitter_address()) @ HelloWorldError::InvalidForeignEmitter,
+        constraint = foreign_emitter.is_newer(posted.sequence()) @ HelloWorldError::StaleSequence
     )]
     /// Foreign emitter account. The posted message's `emitter_address` must
     /// agree with the one we have registered for this message's `emitter_chain`
-    /// (chain ID). Read-only.
+    /// (chain ID), and its sequence must be newer than the last one received.
+    /// Mutable.
     pub foreign_emitter: Account<'info, ForeignEmitter>,
 
+    #[account(
+        mut,
+        constraint = !state.paused @ CustomError::Paused,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. Messages are rejected while the program is paused.
+    /// The users funded by the message are credited in their [`UserState`]
+    /// accounts, passed as remaining accounts. Mutable.
+    pub state: Account<'info, State>,
+
     #[account(
         init,
         payer = payer,
@@ -202,6 +356,188 @@ pub struct ReceiveMessage<'info> {
     /// replay with the same sequence.
     pub received: Account<'info, Received>,
 
+    #[account(
+        seeds = [
+            RelayerStake::SEED_PREFIX,
+            payer.key().as_ref()
+        ],
+        bump
+    )]
+    /// Payer's relayer stake account. Only required when the [`State`]
+    /// specifies a nonzero `min_relayer_stake`. Read-only.
+    pub relayer_stake: Option<Account<'info, RelayerStake>>,
+
+    #[account(
+        constraint = state.is_mint_allowed(&mint.key()) @ CustomError::MintNotAllowed
+    )]
+    /// Mint claims are paid in. Only required for
+    /// [HelloWorldMessage::MultiUserInfo] messages, whose amounts are scaled
+    /// to its decimals. Read-only.
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// System program.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(vaa_hash: [u8; 32])]
+pub struct ReceiveHeartbeat<'info> {
+    #[account(mut)]
+    /// Payer will initialize the health state account on the first heartbeat.
+    pub payer: Signer<'info>,
+
+    // Wormhole program.
+    pub wormhole_program: Program<'info, wormhole::program::Wormhole>,
+
+    #[account(
+        seeds = [
+            wormhole::SEED_PREFIX_POSTED_VAA,
+            &vaa_hash
+        ],
+        bump,
+        seeds::program = wormhole_program
+    )]
+    /// Verified Wormhole message account. Read-only.
+    pub posted: Account<'info, wormhole::PostedVaa<HelloWorldMessage>>,
+
+    #[account(
+        mut,
+        seeds = [
+            ForeignEmitter::SEED_PREFIX,
+            &posted.emitter_chain().to_le_bytes()[..]
+        ],
+        bump,
+        constraint = foreign_emitter.verify(posted.emitter_address()) @ HelloWorldError::InvalidForeignEmitter,
+        constraint = foreign_emitter.is_newer(posted.sequence()) @ HelloWorldError::StaleSequence
+    )]
+    /// Foreign emitter account. Same checks as in [`ReceiveMessage`].
+    /// Mutable.
+    pub foreign_emitter: Account<'info, ForeignEmitter>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [
+            HealthState::SEED_PREFIX,
+            &posted.emitter_chain().to_le_bytes()[..]
+        ],
+        bump,
+        space = HealthState::MAXIMUM_SIZE
+    )]
+    /// Health state account of the message's `emitter_chain`. Mutable.
+    pub health: Account<'info, HealthState>,
+
+    #[account(
+        mut,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. Records the slot of the heartbeat. Mutable.
+    pub state: Account<'info, State>,
+
     /// System program.
     pub system_program: Program<'info, System>,
-}
\ No newline at end of file
+}
+#[derive(Accounts)]
+pub struct SetMinRelayerStake<'info> {
+    /// Owner set in the [`State`] account.
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. This program requires that the `owner` specified in the
+    /// context equals the pubkey specified in this account. Mutable.
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct StakeRelayer<'info> {
+    #[account(mut)]
+    /// Relayer locking lamports. Signer for creating the [`RelayerStake`]
+    /// account if it does not exist yet.
+    pub relayer: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        seeds = [
+            RelayerStake::SEED_PREFIX,
+            relayer.key().as_ref()
+        ],
+        bump,
+        space = RelayerStake::MAXIMUM_SIZE
+    )]
+    /// Relayer stake account. Staked lamports are held directly by this
+    /// account on top of its rent-exempt reserve.
+    pub relayer_stake: Account<'info, RelayerStake>,
+
+    /// System program.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(relayer: Pubkey)]
+pub struct SlashRelayer<'info> {
+    #[account(mut)]
+    /// Owner set in the [`State`] account. Receives the slashed lamports.
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. This program requires that the `owner` specified in the
+    /// context equals the pubkey specified in this account. Read-only.
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        seeds = [
+            RelayerStake::SEED_PREFIX,
+            relayer.as_ref()
+        ],
+        bump
+    )]
+    /// Relayer stake account to slash.
+    pub relayer_stake: Account<'info, RelayerStake>,
+}
+
+#[derive(Accounts)]
+#[instruction(chain: u16, sequence: u64)]
+pub struct MigrateReceived<'info> {
+    #[account(mut)]
+    /// Owner set in the [`State`] account. Pays for the [`Received`] account's
+    /// additional rent.
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    /// State account. This program requires that the `owner` specified in the
+    /// context equals the pubkey specified in this account. Read-only.
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [
+            Received::SEED_PREFIX,
+            &chain.to_le_bytes()[..],
+            &sequence.to_le_bytes()[..]
+        ],
+        bump
+    )]
+    /// CHECK: Received account to grow. Not deserialized, as an older layout
+    /// may not fit the current one until it is migrated.
+    pub received: UncheckedAccount<'info>,
+
+    /// System program.
+    pub system_program: Program<'info, System>,
+}
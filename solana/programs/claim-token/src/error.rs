@@ -38,4 +38,18 @@ pub enum HelloWorldError {
     #[msg("InvalidMessage")]
     /// Deserialized message has unexpected payload type.
     InvalidMessage,
+
+    #[msg("InsufficientRelayerStake")]
+    /// Relayer has not locked enough lamports in its stake account.
+    InsufficientRelayerStake,
+
+    #[msg("StaleSequence")]
+    /// Posted message's sequence is not newer than the last one received from
+    /// its emitter.
+    StaleSequence,
+
+    #[msg("InsufficientConsistency")]
+    /// Posted message was emitted at a lower consistency level than the state
+    /// requires.
+    InsufficientConsistency,
 }
\ No newline at end of file
@@ -0,0 +1,214 @@
+use anchor_lang::prelude::*;
+
+#[event]
+/// Emitted by [`claim_token`](crate::claim_token) and its variants after the
+/// transfer.
+pub struct TokenClaimed {
+    /// Owner of the destination token account.
+    pub user: Pubkey,
+    /// Amount transferred, after the protocol fee.
+    pub amount: u64,
+    /// Destination token account.
+    pub recipient: Pubkey,
+    /// Reference attached by [`claim_with_memo`](crate::claim_with_memo), or
+    /// [`NO_MEMO`](crate::NO_MEMO).
+    pub memo: [u8; 32],
+    /// Correlation id of the claimed allocation, or
+    /// [`NO_CORRELATION_ID`](crate::NO_CORRELATION_ID).
+    pub correlation_id: [u8; 16],
+}
+
+#[event]
+/// Emitted by [`receive_message`](crate::receive_message) after the message is
+/// saved to its [Received](crate::state::Received) account.
+pub struct MessageReceived {
+    /// Keccak256 hash of verified Wormhole message.
+    pub vaa_hash: [u8; 32],
+    /// AKA nonce.
+    pub batch_id: u32,
+    /// Destination token accounts credited by a `MultiUserInfo` message, one
+    /// per entry in order. Empty for other messages.
+    pub users: Vec<Pubkey>,
+    /// Whole units credited to each of `users`, in the mint's decimals.
+    pub amounts: Vec<u64>,
+}
+
+#[event]
+#[derive(Default)]
+/// Emitted once by [`batch_claim`](crate::batch_claim) in place of a
+/// [`TokenClaimed`] per user when the state's `event_level` is
+/// `EVENT_LEVEL_BATCH`.
+pub struct BatchClaimEvent {
+    /// Number of users paid.
+    pub count: u32,
+    /// Total amount transferred, after protocol fees.
+    pub total: u64,
+    /// Destination token accounts, at most
+    /// [`MAX_BATCH_CLAIM`](crate::MAX_BATCH_CLAIM).
+    pub recipients: Vec<Pubkey>,
+}
+
+impl BatchClaimEvent {
+    pub fn record(&mut self, recipient: Pubkey, amount: u64) {
+        self.count += 1;
+        self.total = self.total.saturating_add(amount);
+        self.recipients.push(recipient);
+    }
+}
+
+#[event]
+/// Emitted by [`poke_expiry`](crate::poke_expiry) once the claim deadline is
+/// within the state's `expiry_warning_secs`.
+pub struct ExpiryWarning {
+    /// Unix timestamp after which allocations can no longer be claimed.
+    pub claim_deadline: i64,
+    /// Seconds left until `claim_deadline`.
+    pub seconds_left: i64,
+}
+
+#[event]
+/// Emitted alongside the specific event of every critical action, so a single
+/// subscription can drive incident paging.
+pub struct CriticalAlert {
+    /// Stable numeric code of the action. See the `CODE_*` constants.
+    pub code: u16,
+    /// Action-specific detail. See the `CODE_*` constants.
+    pub detail: u64,
+}
+
+impl CriticalAlert {
+    /// [`set_paused`](crate::set_paused) was called. `detail` is `1` when
+    /// pausing and `0` when unpausing.
+    pub const CODE_PAUSE: u16 = 1;
+    /// [`register_emitter`](crate::register_emitter),
+    /// [`update_emitter`](crate::update_emitter) or
+    /// [`deregister_emitter`](crate::deregister_emitter) changed a foreign
+    /// emitter. `detail` is the emitter's chain ID.
+    pub const CODE_EMITTER_CHANGE: u16 = 2;
+
+    pub fn pause(paused: bool) -> Self {
+        Self {
+            code: Self::CODE_PAUSE,
+            detail: paused.into(),
+        }
+    }
+
+    pub fn emitter_change(chain: u16) -> Self {
+        Self {
+            code: Self::CODE_EMITTER_CHANGE,
+            detail: chain.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use anchor_lang::{Discriminator, Event};
+
+    #[test]
+    fn test_token_claimed() -> Result<()> {
+        let event = TokenClaimed {
+            user: Pubkey::new_unique(),
+            amount: 10_000,
+            recipient: Pubkey::new_unique(),
+            memo: [7u8; 32],
+            correlation_id: [4u8; 16],
+        };
+
+        // Program logs carry the discriminator followed by the event fields.
+        let data = event.data();
+        assert_eq!(data[..8], TokenClaimed::DISCRIMINATOR);
+
+        let decoded = TokenClaimed::deserialize(&mut &data[8..])?;
+        assert_eq!(decoded.user, event.user);
+        assert_eq!(decoded.amount, event.amount);
+        assert_eq!(decoded.recipient, event.recipient);
+        assert_eq!(decoded.memo, event.memo);
+        assert_eq!(decoded.correlation_id, event.correlation_id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_received() -> Result<()> {
+        let user = Pubkey::new_unique();
+        let event = MessageReceived {
+            vaa_hash: [7u8; 32],
+            batch_id: 0,
+            users: vec![user, Pubkey::new_unique(), user],
+            amounts: vec![10_000, 2, 1],
+        };
+
+        let data = event.data();
+        assert_eq!(data[..8], MessageReceived::DISCRIMINATOR);
+
+        let decoded = MessageReceived::deserialize(&mut &data[8..])?;
+        assert_eq!(decoded.vaa_hash, event.vaa_hash);
+        assert_eq!(decoded.batch_id, event.batch_id);
+        assert_eq!(decoded.users, event.users);
+        assert_eq!(decoded.amounts, event.amounts);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_claim_event() -> Result<()> {
+        let recipients = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut event = BatchClaimEvent::default();
+        event.record(recipients[0], 10_000);
+        event.record(recipients[1], 11_000);
+        assert_eq!(event.count, 2);
+        assert_eq!(event.total, 21_000);
+
+        let data = event.data();
+        assert_eq!(data[..8], BatchClaimEvent::DISCRIMINATOR);
+
+        let decoded = BatchClaimEvent::deserialize(&mut &data[8..])?;
+        assert_eq!(decoded.count, 2);
+        assert_eq!(decoded.total, 21_000);
+        assert_eq!(decoded.recipients, recipients);
+
+        // The total saturates instead of overflowing.
+        event.record(Pubkey::new_unique(), u64::MAX);
+        assert_eq!(event.count, 3);
+        assert_eq!(event.total, u64::MAX);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry_warning() -> Result<()> {
+        let event = ExpiryWarning {
+            claim_deadline: 1_700_000_000,
+            seconds_left: 100,
+        };
+
+        let data = event.data();
+        assert_eq!(data[..8], ExpiryWarning::DISCRIMINATOR);
+
+        let decoded = ExpiryWarning::deserialize(&mut &data[8..])?;
+        assert_eq!(decoded.claim_deadline, event.claim_deadline);
+        assert_eq!(decoded.seconds_left, event.seconds_left);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_critical_alert() -> Result<()> {
+        let alert = CriticalAlert::pause(true);
+        assert_eq!(alert.code, CriticalAlert::CODE_PAUSE);
+        assert_eq!(alert.detail, 1);
+        assert_eq!(CriticalAlert::pause(false).detail, 0);
+
+        let alert = CriticalAlert::emitter_change(2);
+        assert_eq!(alert.code, CriticalAlert::CODE_EMITTER_CHANGE);
+        assert_eq!(alert.detail, 2);
+
+        // Codes are part of the public interface and must never change.
+        assert_eq!(CriticalAlert::CODE_PAUSE, 1);
+        assert_eq!(CriticalAlert::CODE_EMITTER_CHANGE, 2);
+
+        Ok(())
+    }
+}
@@ -1,54 +1,700 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, Transfer};
-use anchor_spl::token_interface::{TokenAccount, TokenInterface};
-use std::collections::HashMap;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program::{set_return_data, MAX_RETURN_DATA};
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::associated_token::{get_associated_token_address_with_program_id, AssociatedToken};
+use anchor_spl::token::{spl_token::native_mint, Token};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
 use wormhole_anchor_sdk::wormhole;
 
 pub use context::*;
 pub use error::*;
+pub use event::*;
 pub use message::*;
 pub use state::*;
 
 pub mod context;
 pub mod error;
+pub mod event;
 pub mod message;
 pub mod state;
 
 declare_id!("FwUNgovwW4yHXfqJiVWuWgCpJfeSqtfsPzDBTL9LGX6g");
 
+/// Size of one `(user, amount)` entry packed by [`pack_user_amounts`].
+pub const USER_AMOUNT_LEN: usize = 32 + 8;
+
+/// AKA `b"vault"`. Seeds of the PDA owning the vault token account that
+/// claims are paid from.
+pub const SEED_PREFIX_VAULT: &[u8; 5] = b"vault";
+
+/// AKA `b"native"`. Seeds of the temporary wrapped SOL account that
+/// [`claim_native`](claim_token::claim_native) pays into and unwraps, followed
+/// by the claiming wallet.
+pub const SEED_PREFIX_NATIVE: &[u8; 6] = b"native";
+
+/// Memo of claims made without [`claim_with_memo`](claim_token::claim_with_memo).
+pub const NO_MEMO: [u8; 32] = [0; 32];
+
+/// Correlation id of allocations credited without one.
+pub const NO_CORRELATION_ID: [u8; 16] = [0; 16];
+
+/// Basis points in a whole claim, the maximum of `State::fee_bps`.
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+/// Maximum number of users [`batch_claim`](claim_token::batch_claim) pays out
+/// in one call, keeping the transfers within the default compute budget.
+pub const MAX_BATCH_CLAIM: usize = 8;
+
+/// Maximum number of users [`batch_user_view`](claim_token::batch_user_view)
+/// reads in one call, bounded by the size of the return data.
+pub const MAX_BATCH_VIEW_USERS: usize = MAX_RETURN_DATA / USER_AMOUNT_LEN;
+
 #[program]
 pub mod claim_token {
 
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>, owner: Pubkey) -> Result<()> {
+    pub fn initialize(ctx: Context<Initialize>, owner: Pubkey, allowed_mint: Pubkey) -> Result<()> {
         let state = &mut ctx.accounts.state;
         state.owner = owner;
-        state.users = HashMap::new();
+        state.allowed_mint = allowed_mint;
+        state.paused = false;
+        state.pending_owner = None;
+        state.min_claimer_sol = 0;
+        state.require_registered_destination = false;
+        state.claim_deadline = 0;
+        state.event_level = State::EVENT_LEVEL_CLAIM;
+        state.expiry_warning_secs = 0;
+        state.expiry_warned_deadline = 0;
+        state.min_claim_amount = 0;
+        state.require_kyc = false;
+        state.fee_bps = 0;
+        state.fee_recipient = Pubkey::default();
+        state.total_claimed = 0;
+        state.claim_count = 0;
+        state.merkle_root = [0; 32];
+        state.operator = Pubkey::default();
+        state.total_received = 0;
+        state.window_secs = 0;
+        state.window_limit = 0;
+        state.window_start = 0;
+        state.window_credited = 0;
+        state.required_consistency = 0;
+        state.require_memo = false;
+        state.outstanding = 0;
+        state.min_relayer_stake = 0;
+        state.vault_bump = *ctx
+            .bumps
+            .get("vault_authority")
+            .ok_or(HelloWorldError::BumpNotFound)?;
+        Ok(())
+    }
+
+    pub fn set_event_level(ctx: Context<SetEventLevel>, event_level: u8) -> Result<()> {
+        require!(
+            event_level <= State::EVENT_LEVEL_BATCH,
+            CustomError::InvalidEventLevel
+        );
+        ctx.accounts.state.event_level = event_level;
+        Ok(())
+    }
+
+    pub fn set_claim_deadline(ctx: Context<SetClaimDeadline>, claim_deadline: i64) -> Result<()> {
+        ctx.accounts.state.claim_deadline = claim_deadline;
+        Ok(())
+    }
+
+    /// Sets the expiry of one allocation, overriding the one its messages
+    /// set. Zero removes it.
+    pub fn set_user_expiry(ctx: Context<SetUserExpiry>, expiry: i64) -> Result<()> {
+        ctx.accounts.user_state.expiry = expiry;
+        Ok(())
+    }
+
+    /// Has the claims of `user`'s allocation paid into `recipient`, for
+    /// custodial setups. Zero pays `user` again.
+    pub fn set_recipient(ctx: Context<SetRecipient>, recipient: Pubkey) -> Result<()> {
+        ctx.accounts.user_state.recipient = recipient;
+        Ok(())
+    }
+
+    /// Takes `fee_discount_bps` off the protocol fee of one allocation's
+    /// claims.
+    pub fn set_fee_discount(ctx: Context<SetFeeDiscount>, fee_discount_bps: u16) -> Result<()> {
+        require!(fee_discount_bps <= MAX_FEE_BPS, CustomError::InvalidFee);
+        ctx.accounts.user_state.fee_discount_bps = fee_discount_bps;
+        Ok(())
+    }
+
+    /// Credits `amount` to a test allocation of `user`, for canary testing a
+    /// live deployment. It is claimed like any other allocation until
+    /// [`purge_test_allocations`](claim_token::purge_test_allocations) drops
+    /// it.
+    pub fn inject_test_allocation(
+        ctx: Context<InjectTestAllocation>,
+        user: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+
+        let state = &mut ctx.accounts.state;
+        let mut user_state = load_user_state(
+            &ctx.accounts.user_state.to_account_info(),
+            &user,
+            &state.allowed_mint,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+        )?;
+        require_keys_eq!(
+            user_state.mint,
+            state.allowed_mint,
+            CustomError::MintMismatch
+        );
+        // Test allocations are purged whole, so they never share a user state
+        // with a real one.
+        require!(
+            user_state.is_test || (user_state.amount == 0 && user_state.claimed == 0),
+            CustomError::NotTestAllocation
+        );
+
+        user_state.amount = user_state
+            .amount
+            .checked_add(amount)
+            .ok_or(CustomError::AmountOverflow)?;
+        user_state.is_test = true;
+        state.outstanding = state
+            .outstanding
+            .checked_add(amount)
+            .ok_or(CustomError::AmountOverflow)?;
+        user_state.exit(ctx.program_id)?;
+        msg!("Injected test allocation: {} for {}", amount, user);
+        Ok(())
+    }
+
+    pub fn set_expiry_warning_secs(
+        ctx: Context<SetExpiryWarningSecs>,
+        expiry_warning_secs: i64,
+    ) -> Result<()> {
+        ctx.accounts.state.expiry_warning_secs = expiry_warning_secs;
+        Ok(())
+    }
+
+    /// Caps the amount [`receive_message`](claim_token::receive_message) may
+    /// credit to `window_limit` per `window_secs`. A zero limit disables the
+    /// cap. Starts a new window.
+    pub fn set_rate_limit(
+        ctx: Context<SetRateLimit>,
+        window_secs: i64,
+        window_limit: u64,
+    ) -> Result<()> {
+        require!(window_secs > 0, CustomError::InvalidAmount);
+
+        let state = &mut ctx.accounts.state;
+        state.window_secs = window_secs;
+        state.window_limit = window_limit;
+        state.window_start = Clock::get()?.unix_timestamp;
+        state.window_credited = 0;
+        Ok(())
+    }
+
+    /// Sets the lowest Wormhole consistency level, as the u8 representation of
+    /// [`Finality`](wormhole::Finality), that
+    /// [`receive_message`](claim_token::receive_message) accepts.
+    pub fn set_required_consistency(
+        ctx: Context<SetRequiredConsistency>,
+        required_consistency: u8,
+    ) -> Result<()> {
+        ctx.accounts.state.required_consistency = required_consistency;
+        Ok(())
+    }
+
+    pub fn poke_expiry(ctx: Context<PokeExpiry>) -> Result<()> {
+        // Anyone may poke. The warning fires at most once per deadline, and
+        // pokes outside the warning window are no-ops.
+        let state = &mut ctx.accounts.state;
+        let now = Clock::get()?.unix_timestamp;
+        if state.should_warn_expiry(now) {
+            state.expiry_warned_deadline = state.claim_deadline;
+            emit!(ExpiryWarning {
+                claim_deadline: state.claim_deadline,
+                seconds_left: state.claim_deadline - now,
+            });
+        }
+        Ok(())
+    }
+
+    /// Drops the allocations of the [`UserState`] accounts passed as remaining
+    /// accounts, closing them to the owner. Each must be past its own expiry
+    /// or the state's claim deadline.
+    pub fn sweep_expired<'info>(
+        ctx: Context<'_, '_, '_, 'info, SweepExpired<'info>>,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Allocations are paid from the vault, so unclaimed tokens never left
+        // it. Dropping the allocations frees them for new allocations.
+        let mut swept = 0u64;
+        for info in ctx.remaining_accounts {
+            let user_state = Account::<UserState>::try_from(info)?;
+            require!(
+                state.is_expired(now) || user_state.is_expired(now),
+                CustomError::ClaimNotExpired
+            );
+            swept = swept.saturating_add(user_state.amount);
+            state.outstanding = state.outstanding.saturating_sub(user_state.amount);
+            user_state.close(ctx.accounts.owner.to_account_info())?;
+        }
+        msg!("Swept expired allocations: {}", swept);
+        Ok(())
+    }
+
+    /// Drops the test allocations of the [`UserState`] accounts passed as
+    /// remaining accounts, closing them to the owner.
+    pub fn purge_test_allocations<'info>(
+        ctx: Context<'_, '_, '_, 'info, PurgeTestAllocations<'info>>,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        let mut purged = 0u64;
+        for info in ctx.remaining_accounts {
+            let user_state = Account::<UserState>::try_from(info)?;
+            require!(user_state.is_test, CustomError::NotTestAllocation);
+            purged = purged.saturating_add(user_state.amount);
+            state.outstanding = state.outstanding.saturating_sub(user_state.amount);
+            user_state.close(ctx.accounts.owner.to_account_info())?;
+        }
+        msg!("Purged test allocations: {}", purged);
+        Ok(())
+    }
+
+    pub fn propose_owner(ctx: Context<ProposeOwner>, new_owner: Pubkey) -> Result<()> {
+        ctx.accounts.state.pending_owner = Some(new_owner);
+        msg!("Proposed owner: {}", new_owner);
+        Ok(())
+    }
+
+    pub fn accept_owner(ctx: Context<AcceptOwner>) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        state.owner = ctx.accounts.pending_owner.key();
+        state.pending_owner = None;
+        msg!("Accepted owner: {}", state.owner);
+        Ok(())
+    }
+
+    /// Sets the operator, who may pause the program and register emitters
+    /// besides the owner. The default pubkey removes the operator.
+    pub fn set_operator(ctx: Context<SetOperator>, operator: Pubkey) -> Result<()> {
+        ctx.accounts.state.operator = operator;
+        msg!("Operator: {}", operator);
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.state.paused = paused;
+
+        emit!(CriticalAlert::pause(paused));
+        Ok(())
+    }
+
+    pub fn set_min_claimer_sol(ctx: Context<SetMinClaimerSol>, min_claimer_sol: u64) -> Result<()> {
+        ctx.accounts.state.min_claimer_sol = min_claimer_sol;
+        Ok(())
+    }
+
+    pub fn set_min_claim_amount(
+        ctx: Context<SetMinClaimAmount>,
+        min_claim_amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.state.min_claim_amount = min_claim_amount;
+        Ok(())
+    }
+
+    /// Holds allocations credited above `review_threshold` for review, see
+    /// [`approve_claim`](claim_token::approve_claim). Zero disables reviews.
+    pub fn set_review_threshold(
+        ctx: Context<SetReviewThreshold>,
+        review_threshold: u64,
+    ) -> Result<()> {
+        ctx.accounts.state.review_threshold = review_threshold;
+        Ok(())
+    }
+
+    /// Approves an allocation held for review, letting its claims through.
+    pub fn approve_claim(ctx: Context<ApproveClaim>) -> Result<()> {
+        ctx.accounts.user_state.pending_review = false;
+        Ok(())
+    }
+
+    pub fn set_require_registered_destination(
+        ctx: Context<SetRequireRegisteredDestination>,
+        require_registered_destination: bool,
+    ) -> Result<()> {
+        ctx.accounts.state.require_registered_destination = require_registered_destination;
+        Ok(())
+    }
+
+    pub fn set_fee(ctx: Context<SetFee>, fee_bps: u16, fee_recipient: Pubkey) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, CustomError::InvalidFee);
+        let state = &mut ctx.accounts.state;
+        state.fee_bps = fee_bps;
+        state.fee_recipient = fee_recipient;
+        Ok(())
+    }
+
+    /// Pays referrers `referral_bps` of what their referred users claim,
+    /// after the protocol fee. Zero pays no referrals.
+    pub fn set_referral_bps(ctx: Context<SetReferralBps>, referral_bps: u16) -> Result<()> {
+        require!(referral_bps <= MAX_FEE_BPS, CustomError::InvalidFee);
+        ctx.accounts.state.referral_bps = referral_bps;
+        Ok(())
+    }
+
+    pub fn set_require_kyc(ctx: Context<SetRequireKyc>, require_kyc: bool) -> Result<()> {
+        ctx.accounts.state.require_kyc = require_kyc;
+        Ok(())
+    }
+
+    pub fn set_require_memo(ctx: Context<SetRequireMemo>, require_memo: bool) -> Result<()> {
+        ctx.accounts.state.require_memo = require_memo;
+        Ok(())
+    }
+
+    pub fn set_merkle_root(ctx: Context<SetMerkleRoot>, merkle_root: [u8; 32]) -> Result<()> {
+        ctx.accounts.state.merkle_root = merkle_root;
+        Ok(())
+    }
+
+    pub fn set_kyc_tier(
+        ctx: Context<SetKycTier>,
+        _wallet: Pubkey,
+        tier: u8,
+        tier_limit: u64,
+    ) -> Result<()> {
+        // Cumulative claims carry over when the tier changes.
+        let kyc_tier = &mut ctx.accounts.kyc_tier;
+        kyc_tier.tier = tier;
+        kyc_tier.tier_limit = tier_limit;
+        Ok(())
+    }
+
+    pub fn register_destination(
+        ctx: Context<RegisterDestination>,
+        destination: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.destination_registry.destination = destination;
+        Ok(())
+    }
+
+    pub fn deregister_destination(
+        _ctx: Context<DeregisterDestination>,
+        _destination: Pubkey,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Blocks `wallet` from claiming, whatever its allocations.
+    pub fn deny(ctx: Context<Deny>, wallet: Pubkey) -> Result<()> {
+        ctx.accounts.deny_entry.wallet = wallet;
+        Ok(())
+    }
+
+    /// Lifts a [`deny`](claim_token::deny) of `wallet`.
+    pub fn allow(_ctx: Context<Allow>, _wallet: Pubkey) -> Result<()> {
+        Ok(())
+    }
+
+    /// Decommissions the deployment, refunding the state account's rent to
+    /// the owner. A fresh [`initialize`](claim_token::initialize) can follow.
+    pub fn close_state(ctx: Context<CloseState>) -> Result<()> {
+        // Tokens left in the vault could no longer be paid out.
+        require!(ctx.accounts.vault.amount == 0, CustomError::VaultNotEmpty);
+        require!(
+            !ctx.accounts.state.has_pending_claims(),
+            CustomError::AllocationNotClaimed
+        );
+        Ok(())
+    }
+
+    pub fn close_allocation(_ctx: Context<CloseAllocation>) -> Result<()> {
+        // The user state account is closed on exit, refunding its rent to the
+        // recipient.
+        Ok(())
+    }
+
+    pub fn claim_token(ctx: Context<ClaimToken>, amount: u64) -> Result<ClaimResult> {
+        transfer_claim(ctx.accounts, amount, NO_MEMO)
+    }
+
+    /// Claims like [`claim_token`](claim_token::claim_token), recording `memo`
+    /// (e.g. an invoice reference) in the emitted [`TokenClaimed`] event.
+    pub fn claim_with_memo(
+        ctx: Context<ClaimToken>,
+        amount: u64,
+        memo: [u8; 32],
+    ) -> Result<ClaimResult> {
+        transfer_claim(ctx.accounts, amount, memo)
+    }
+
+    pub fn claim_token_to_new_account(
+        ctx: Context<ClaimTokenToNewAccount>,
+        amount: u64,
+    ) -> Result<ClaimResult> {
+        require_keys_eq!(
+            ctx.accounts.claim.user.key(),
+            ctx.accounts.recipient.recipient.key(),
+            CustomError::RecipientMismatch
+        );
+        transfer_claim(&mut ctx.accounts.claim, amount, NO_MEMO)
+    }
+
+    /// Claims `amount` of a wrapped SOL allocation as native SOL. The tokens
+    /// are paid into the temporary `native` account, which is then closed to
+    /// `wallet`, unwrapping them and refunding its rent. `claim.user` and any
+    /// balance it holds are left untouched.
+    pub fn claim_native(ctx: Context<ClaimNative>, amount: u64) -> Result<ClaimResult> {
+        // Allocations paid into a recipient are never unwrapped to the wallet.
+        require!(
+            !has_recipient(&ctx.accounts.claim),
+            CustomError::RecipientMismatch
+        );
+        let native = ctx.accounts.native.to_account_info();
+        let result = transfer_claim_to(&mut ctx.accounts.claim, native.clone(), amount, NO_MEMO)?;
+
+        let wallet = ctx.accounts.wallet.to_account_info();
+        token_interface::close_account(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::CloseAccount {
+                account: native,
+                destination: wallet.clone(),
+                authority: wallet,
+            },
+        ))?;
+        Ok(result)
+    }
+
+    /// Claims the `amount` of the leaf of `claim.user` in the state's Merkle
+    /// tree. Each leaf can be claimed once per root.
+    pub fn claim_with_proof(
+        ctx: Context<ClaimWithProof>,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let claim = &mut ctx.accounts.claim;
+        let merkle_root = claim.state.merkle_root;
+        require!(
+            merkle_root != [0; 32]
+                && verify_merkle_proof(
+                    &proof,
+                    &merkle_root,
+                    &merkle_leaf(&claim.user.key(), amount)
+                ),
+            CustomError::InvalidProof
+        );
+
+        check_claim(claim, amount)?;
+        let user = claim.user.to_account_info();
+        pay_claim(claim, user, amount, NO_MEMO)?;
+        ctx.accounts.claimed_leaf.amount = amount;
+        Ok(())
+    }
+
+    pub fn claim_token_idempotent(
+        ctx: Context<ClaimTokenIdempotent>,
+        _idempotency_key: [u8; 32],
+        amount: u64,
+    ) -> Result<()> {
+        // A retried claim with the same key and signer returns the prior
+        // result instead of transferring again.
+        let receipt = &mut ctx.accounts.receipt;
+        if !receipt.claimed {
+            transfer_claim(&mut ctx.accounts.claim, amount, NO_MEMO)?;
+            receipt.amount = amount;
+            receipt.claimed = true;
+        }
+
+        set_return_data(&receipt.amount.to_le_bytes());
         Ok(())
     }
 
-    pub fn claim_token(ctx: Context<ClaimToken>) -> Result<()> {
-        let state = &ctx.accounts.state;
+    pub fn batch_claim<'info>(ctx: Context<'_, '_, '_, 'info, BatchClaim<'info>>) -> Result<()> {
+        // Remaining accounts are (destination token account, owning wallet,
+        // user state, deny entry) quadruples, matching the `user`, `claimer`,
+        // `user_state` and `deny_entry` of [`ClaimToken`]. Every owning wallet
+        // signs, like `claimer` does.
+        let batch = ctx.remaining_accounts.chunks_exact(4);
+        require!(
+            batch.remainder().is_empty(),
+            CustomError::InvalidRemainingAccounts
+        );
+        require!(batch.len() <= MAX_BATCH_CLAIM, CustomError::TooManyAccounts);
+
+        let accounts = ctx.accounts;
+        let now = Clock::get()?.unix_timestamp;
+        require!(!accounts.state.is_expired(now), CustomError::ClaimExpired);
+        // Batches carry no registry accounts, so they can't prove their
+        // destinations are approved.
         require!(
-            HashMap::contains_key(&state.users, &ctx.accounts.user.key()),
-            CustomError::Unauthorized
+            accounts.state.is_destination_allowed(false),
+            CustomError::DestinationNotRegistered
         );
+        // Nor KYC tiers.
+        require!(!accounts.state.require_kyc, CustomError::KycLimitExceeded);
+        // Nor memos.
         require!(
-            state.owner == ctx.accounts.owner.key(),
-            CustomError::InvalidOwner
+            accounts.state.accepts_memo(&NO_MEMO),
+            CustomError::MemoRequired
         );
 
-        let amount = *HashMap::get(&state.users, &ctx.accounts.user.key()).unwrap();
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.owner.to_account_info(),
-            to: ctx.accounts.user.to_account_info(),
-            authority: ctx.accounts.owner.to_account_info(),
+        let aggregate = accounts.state.event_level == State::EVENT_LEVEL_BATCH;
+        let mut batch_event = BatchClaimEvent::default();
+        for user_accounts in batch {
+            let user = InterfaceAccount::<TokenAccount>::try_from(&user_accounts[0])?;
+            let claimer = &user_accounts[1];
+            let mut user_state = Account::<UserState>::try_from(&user_accounts[2])?;
+            let deny_entry = &user_accounts[3];
+            require_keys_eq!(
+                claimer.key(),
+                user.owner,
+                CustomError::InvalidRemainingAccounts
+            );
+            require!(claimer.is_signer, ErrorCode::AccountNotSigner);
+            require_keys_eq!(
+                user_state.user,
+                user.key(),
+                CustomError::InvalidRemainingAccounts
+            );
+            require_keys_eq!(
+                deny_entry.key(),
+                DenyEntry::address(claimer.key).0,
+                CustomError::InvalidRemainingAccounts
+            );
+            require!(deny_entry.data_is_empty(), CustomError::Denied);
+            require_keys_eq!(user.mint, accounts.vault.mint, CustomError::MintMismatch);
+            require_keys_eq!(
+                user_state.mint,
+                accounts.vault.mint,
+                CustomError::MintMismatch
+            );
+
+            // Users with nothing left to claim, including those whose
+            // allocation expired or awaits review, are skipped rather than
+            // failing the whole batch. As are those paid into a recipient or
+            // owing a referrer, which batches carry no account for.
+            let amount = user_state.amount;
+            if amount == 0
+                || !accounts.state.meets_min_claim(amount)
+                || user_state.is_expired(now)
+                || user_state.pending_review
+                || user_state.destination() != user.key()
+                || (user_state.referrer_of(&user.owner).is_some()
+                    && accounts.state.referral_bps != 0)
+            {
+                continue;
+            }
+            require!(
+                accounts.state.is_funded_claimer(claimer.lamports()),
+                CustomError::InsufficientSol
+            );
+            accounts.state.debit(&mut user_state, amount)?;
+            accounts.state.record_claim(amount)?;
+            // Written back right away, so a user repeated in the batch finds
+            // its allocation already drawn down.
+            user_state.exit(ctx.program_id)?;
+
+            let (fee, amount) = accounts
+                .state
+                .split_fee(amount, user_state.fee_discount_bps);
+            let vault_bump = accounts.state.vault_bump;
+            transfer_fee(
+                &accounts.vault,
+                &accounts.fee_account,
+                &accounts.mint,
+                &accounts.vault_authority,
+                &accounts.token_program,
+                vault_bump,
+                fee,
+            )?;
+            transfer_from_vault(
+                &accounts.vault,
+                user_accounts[0].clone(),
+                &accounts.mint,
+                &accounts.vault_authority,
+                &accounts.token_program,
+                vault_bump,
+                amount,
+            )?;
+
+            if aggregate {
+                batch_event.record(user.key(), amount);
+            } else {
+                emit!(TokenClaimed {
+                    user: user.owner,
+                    amount,
+                    recipient: user.key(),
+                    memo: NO_MEMO,
+                    correlation_id: user_state.correlation_id,
+                });
+            }
+        }
+
+        if aggregate {
+            emit!(batch_event);
+        }
+        Ok(())
+    }
+
+    /// Deposits `amount` from `source` into the vault that claims are paid
+    /// from. Anyone may fund the vault.
+    pub fn fund_vault(ctx: Context<FundVault>, amount: u64) -> Result<()> {
+        require!(amount > 0, CustomError::InvalidAmount);
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.source.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.depositor.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(
+            CpiContext::new(cpi_program, cpi_accounts),
+            amount,
+            ctx.accounts.mint.decimals,
+        )
+    }
+
+    /// Returns the remaining allocation of the signer's token account as a
+    /// little-endian u64 in the return data, for clients to simulate.
+    pub fn get_claimable(ctx: Context<GetClaimable>) -> Result<()> {
+        let remaining =
+            read_user_state(&ctx.accounts.user_state)?.map_or(0, |user_state| user_state.amount);
+        set_return_data(&remaining.to_le_bytes());
+        Ok(())
+    }
 
-        token::transfer(cpi_ctx, amount)?;
+    /// Returns the claim status of `user`, for clients to simulate.
+    pub fn view_claim(ctx: Context<ViewClaim>) -> Result<ClaimView> {
+        Ok(ClaimView::new(
+            ctx.accounts.user.key(),
+            read_user_state(&ctx.accounts.user_state)?.as_ref(),
+        ))
+    }
+
+    /// Returns the allocations of the [`UserState`] accounts passed as
+    /// remaining accounts, packed by [`pack_user_amounts`].
+    pub fn batch_user_view(ctx: Context<ViewUsers>) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_BATCH_VIEW_USERS,
+            CustomError::TooManyAccounts
+        );
+
+        let mut user_states = Vec::with_capacity(ctx.remaining_accounts.len());
+        for info in ctx.remaining_accounts {
+            user_states.extend(read_user_state(info)?);
+        }
+        set_return_data(&pack_user_amounts(&user_states));
         Ok(())
     }
 
@@ -57,39 +703,342 @@ pub mod claim_token {
         chain: u16,
         address: [u8; 32],
     ) -> Result<()> {
-        // Foreign emitter cannot share the same Wormhole Chain ID as the
-        // Solana Wormhole program's. And cannot register a zero address.
+        // Save the emitter info into the ForeignEmitter account.
+        save_emitter(&mut ctx.accounts.foreign_emitter, chain, address)?;
+
+        emit!(CriticalAlert::emitter_change(chain));
+
+        // Done.
+        Ok(())
+    }
+
+    /// Rotates the address of an already registered foreign emitter.
+    pub fn update_emitter(
+        ctx: Context<UpdateEmitter>,
+        chain: u16,
+        new_address: [u8; 32],
+    ) -> Result<()> {
+        save_emitter(&mut ctx.accounts.foreign_emitter, chain, new_address)?;
+
+        emit!(CriticalAlert::emitter_change(chain));
+        Ok(())
+    }
+
+    pub fn deregister_emitter(_ctx: Context<DeregisterEmitter>, chain: u16) -> Result<()> {
+        emit!(CriticalAlert::emitter_change(chain));
+        Ok(())
+    }
+
+    pub fn set_min_relayer_stake(
+        ctx: Context<SetMinRelayerStake>,
+        min_relayer_stake: u64,
+    ) -> Result<()> {
+        ctx.accounts.state.min_relayer_stake = min_relayer_stake;
+        Ok(())
+    }
+
+    pub fn stake_relayer(ctx: Context<StakeRelayer>, amount: u64) -> Result<()> {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.relayer.to_account_info(),
+                    to: ctx.accounts.relayer_stake.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let stake = &mut ctx.accounts.relayer_stake;
+        stake.relayer = ctx.accounts.relayer.key();
+        stake.amount = stake
+            .amount
+            .checked_add(amount)
+            .ok_or(CustomError::AmountOverflow)?;
+        Ok(())
+    }
+
+    pub fn slash_relayer(ctx: Context<SlashRelayer>, _relayer: Pubkey, amount: u64) -> Result<()> {
+        let stake = &mut ctx.accounts.relayer_stake;
         require!(
-            chain > 0 && chain != wormhole::CHAIN_ID_SOLANA && !address.iter().all(|&x| x == 0),
-            HelloWorldError::InvalidForeignEmitter,
+            amount <= stake.amount,
+            HelloWorldError::InsufficientRelayerStake
         );
+        stake.amount -= amount;
 
-        // Save the emitter info into the ForeignEmitter account.
-        let emitter = &mut ctx.accounts.foreign_emitter;
-        emitter.chain = chain;
-        emitter.address = address;
+        // The stake account is owned by this program, so its lamports can be
+        // debited directly. Only the staked amount is touched, never the
+        // rent-exempt reserve.
+        **stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx
+            .accounts
+            .owner
+            .to_account_info()
+            .try_borrow_mut_lamports()? += amount;
+        Ok(())
+    }
 
-        // Done.
+    /// Grows the [Received](crate::state::Received) account of the message with
+    /// `sequence` from `chain` to the current layout, zeroing the new tail.
+    /// Accounts already at least that large are left untouched.
+    pub fn migrate_received(
+        ctx: Context<MigrateReceived>,
+        _chain: u16,
+        _sequence: u64,
+    ) -> Result<()> {
+        let received = ctx.accounts.received.to_account_info();
+        require!(
+            received
+                .try_borrow_data()?
+                .starts_with(&Received::DISCRIMINATOR),
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        let new_len = match Received::migrated_len(received.data_len()) {
+            Some(new_len) => new_len,
+            None => return Ok(()),
+        };
+
+        let rent = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(received.lamports());
+        if rent > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.owner.to_account_info(),
+                        to: received.clone(),
+                    },
+                ),
+                rent,
+            )?;
+        }
+        received.realloc(new_len, true)?;
         Ok(())
     }
 
-    pub fn receive_message(ctx: Context<ReceiveMessage>, vaa_hash: [u8; 32]) -> Result<()> {
+    /// Creates the [Config](crate::state::Config) and
+    /// [WormholeEmitter](crate::state::WormholeEmitter) accounts, then posts
+    /// the first `Alive` message, which creates the emitter's sequence account
+    /// [`send_message_alive`](claim_token::send_message_alive) posts with.
+    pub fn initialize_wormhole(ctx: Context<InitializeWormhole>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.wormhole = WormholeAddresses {
+            bridge: ctx.accounts.wormhole_bridge.key(),
+            fee_collector: ctx.accounts.wormhole_fee_collector.key(),
+            sequence: ctx.accounts.wormhole_sequence.key(),
+        };
+        config.batch_id = 0;
+        config.finality = wormhole::Finality::Confirmed as u8;
+
+        ctx.accounts.wormhole_emitter.bump = *ctx
+            .bumps
+            .get("wormhole_emitter")
+            .ok_or(HelloWorldError::BumpNotFound)?;
+
+        post_alive(
+            ctx.accounts.wormhole_program.to_account_info(),
+            wormhole::PostMessage {
+                config: ctx.accounts.wormhole_bridge.to_account_info(),
+                message: ctx.accounts.wormhole_message.to_account_info(),
+                emitter: ctx.accounts.wormhole_emitter.to_account_info(),
+                sequence: ctx.accounts.wormhole_sequence.to_account_info(),
+                payer: ctx.accounts.owner.to_account_info(),
+                fee_collector: ctx.accounts.wormhole_fee_collector.to_account_info(),
+                clock: ctx.accounts.clock.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            ctx.accounts.wormhole_bridge.fee(),
+            wormhole::INITIAL_SEQUENCE,
+            *ctx.bumps
+                .get("wormhole_message")
+                .ok_or(HelloWorldError::BumpNotFound)?,
+            &ctx.accounts.wormhole_emitter,
+            &ctx.accounts.config,
+        )
+    }
+
+    /// Posts an `Alive` message carrying this program's ID, which the
+    /// receiving chain records as a heartbeat, like
+    /// [`receive_heartbeat`](claim_token::receive_heartbeat) does here.
+    pub fn send_message_alive(ctx: Context<SendMessageAlive>) -> Result<()> {
+        post_alive(
+            ctx.accounts.wormhole_program.to_account_info(),
+            wormhole::PostMessage {
+                config: ctx.accounts.wormhole_bridge.to_account_info(),
+                message: ctx.accounts.wormhole_message.to_account_info(),
+                emitter: ctx.accounts.wormhole_emitter.to_account_info(),
+                sequence: ctx.accounts.wormhole_sequence.to_account_info(),
+                payer: ctx.accounts.payer.to_account_info(),
+                fee_collector: ctx.accounts.wormhole_fee_collector.to_account_info(),
+                clock: ctx.accounts.clock.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            ctx.accounts.wormhole_bridge.fee(),
+            ctx.accounts.wormhole_sequence.next_value(),
+            *ctx.bumps
+                .get("wormhole_message")
+                .ok_or(HelloWorldError::BumpNotFound)?,
+            &ctx.accounts.wormhole_emitter,
+            &ctx.accounts.config,
+        )
+    }
+
+    /// Records a relayed message, crediting every entry of a `MultiUserInfo`
+    /// payload to its user state.
+    ///
+    /// Two messages crediting the same user can't race: the runtime runs
+    /// transactions writing the same user state one after the other, so the
+    /// second credit lands on top of the first. Each message creates its own
+    /// received account, so a retried message fails instead of crediting
+    /// twice.
+    pub fn receive_message<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReceiveMessage<'info>>,
+        vaa_hash: [u8; 32],
+    ) -> Result<()> {
+        // Relayers must have enough lamports locked if the owner requires it.
+        let min_relayer_stake = ctx.accounts.state.min_relayer_stake;
+        require!(
+            min_relayer_stake == 0
+                || matches!(
+                    &ctx.accounts.relayer_stake,
+                    Some(stake) if stake.is_sufficient(min_relayer_stake)
+                ),
+            HelloWorldError::InsufficientRelayerStake
+        );
+
         let posted_message = &ctx.accounts.posted;
+        let received = &mut ctx.accounts.received;
+        // Users credited and the amounts they were credited, in entry order.
+        let mut users = Vec::new();
+        let mut amounts = Vec::new();
 
-        if let HelloWorldMessage::Hello { message } = posted_message.data() {
-            // HelloWorldMessage cannot be larger than the maximum size of the account.
-            require!(
-                message.len() <= MESSAGE_MAX_LENGTH,
-                HelloWorldError::InvalidMessage,
-            );
+        match posted_message.data() {
+            HelloWorldMessage::Hello { message } => {
+                // Save message payload. Its length was checked against the
+                // account size before anything was written.
+                received.message = message.clone();
+            }
+            HelloWorldMessage::MultiUserInfo {
+                source_decimals,
+                entries,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            } => {
+                // Amounts are scaled to the decimals of the mint claims are
+                // paid in.
+                let mint = ctx
+                    .accounts
+                    .mint
+                    .as_ref()
+                    .ok_or(ErrorCode::AccountNotEnoughKeys)?;
+                let mint_decimals = mint.decimals;
+
+                // Credit every user. Remaining accounts are the user state
+                // accounts of the entries, in order.
+                require!(
+                    ctx.remaining_accounts.len() == entries.len(),
+                    CustomError::InvalidRemainingAccounts
+                );
+                let total_received = ctx.accounts.state.total_received;
+                for (i, ((user, amount), info)) in
+                    entries.iter().zip(ctx.remaining_accounts).enumerate()
+                {
+                    let mut user_state = load_user_state(
+                        info,
+                        user,
+                        &mint.key(),
+                        &ctx.accounts.payer,
+                        &ctx.accounts.system_program,
+                    )?;
+                    // Allocations stay in the mint they were first credited
+                    // in.
+                    require_keys_eq!(user_state.mint, mint.key(), CustomError::MintMismatch);
+                    // Test allocations are purged whole, so real credits wait
+                    // until they are.
+                    require!(!user_state.is_test, CustomError::TestAllocation);
+                    // Entries without a delegate leave the current one in
+                    // place.
+                    if let Some(delegate) = delegates
+                        .get(i)
+                        .filter(|delegate| **delegate != Pubkey::default())
+                    {
+                        user_state.delegate = Some(*delegate);
+                    }
+                    // As do entries without an expiry.
+                    if let Some(expiry) = expiries.get(i).filter(|expiry| **expiry != 0) {
+                        user_state.expiry = *expiry;
+                    }
+                    // And those without a correlation id.
+                    if let Some(correlation_id) = correlation_ids
+                        .get(i)
+                        .filter(|correlation_id| **correlation_id != NO_CORRELATION_ID)
+                    {
+                        user_state.correlation_id = *correlation_id;
+                    }
+                    // Referrals stick with the first referrer.
+                    if let Some(referrer) = referrers.get(i).filter(|referrer| {
+                        **referrer != Pubkey::default() && user_state.referrer == Pubkey::default()
+                    }) {
+                        user_state.referrer = *referrer;
+                    }
+                    let credited = ctx.accounts.state.credit(
+                        &mut user_state,
+                        *amount,
+                        *source_decimals,
+                        mint_decimals,
+                    )?;
+                    users.push(*user);
+                    amounts.push(credited);
+                    // Written back right away, so a user repeated in the
+                    // message is credited on top of the first entry.
+                    user_state.exit(ctx.program_id)?;
+                }
+                let state = &mut ctx.accounts.state;
+
+                // The whole message counts against the rate limit.
+                let credited = state.total_received - total_received;
+                state.record_window_credit(credited, Clock::get()?.unix_timestamp)?;
+            }
+            // Already rejected by the posted account's constraints.
+            _ => return Err(HelloWorldError::InvalidMessage.into()),
+        }
+
+        // Save batch ID and keccak256 hash.
+        received.batch_id = posted_message.batch_id();
+        received.wormhole_message_hash = vaa_hash;
+
+        // Only newer messages from this emitter are accepted from now on.
+        ctx.accounts.foreign_emitter.last_sequence = Some(posted_message.sequence());
+
+        emit!(MessageReceived {
+            vaa_hash,
+            batch_id: received.batch_id,
+            users,
+            amounts,
+        });
+
+        // Done
+        Ok(())
+    }
+
+    pub fn receive_heartbeat(ctx: Context<ReceiveHeartbeat>, _vaa_hash: [u8; 32]) -> Result<()> {
+        let posted_message = &ctx.accounts.posted;
 
-            // Save batch ID, keccak256 hash and message payload.
-            let received = &mut ctx.accounts.received;
-            received.batch_id = posted_message.batch_id();
-            received.wormhole_message_hash = vaa_hash;
-            received.message = message.clone();
+        if let HelloWorldMessage::Alive { program_id, .. } = posted_message.data() {
+            let clock = Clock::get()?;
+            ctx.accounts.state.record_alive(program_id, clock.slot)?;
+            ctx.accounts
+                .health
+                .record(*program_id, clock.unix_timestamp);
 
-            // Done
+            // Heartbeats share the emitter's sequence, so they can't be
+            // replayed either.
+            ctx.accounts.foreign_emitter.last_sequence = Some(posted_message.sequence());
             Ok(())
         } else {
             Err(HelloWorldError::InvalidMessage.into())
@@ -97,38 +1046,5139 @@ pub mod claim_token {
     }
 }
 
+/// Only the program's upgrade authority may call
+/// [`initialize`](claim_token::initialize), so deployment can't be front-run.
+/// Immutable programs have no upgrade authority and can't be initialized.
+pub fn is_initializer(upgrade_authority: Option<Pubkey>, admin: &Pubkey) -> bool {
+    upgrade_authority == Some(*admin)
+}
+
+/// Saves `chain` and `address` into `emitter`, as done by
+/// [`register_emitter`](claim_token::register_emitter) and
+/// [`update_emitter`](claim_token::update_emitter).
+fn save_emitter(emitter: &mut ForeignEmitter, chain: u16, address: [u8; 32]) -> Result<()> {
+    // Foreign emitter cannot share the same Wormhole Chain ID as the
+    // Solana Wormhole program's. And cannot register a zero address.
+    require!(
+        chain > 0 && chain != wormhole::CHAIN_ID_SOLANA && !address.iter().all(|&x| x == 0),
+        HelloWorldError::InvalidForeignEmitter,
+    );
+    // EVM emitters are 20-byte addresses left-padded to 32 bytes.
+    require!(
+        ForeignEmitter::is_valid_address(chain, &address),
+        HelloWorldError::InvalidForeignEmitter,
+    );
+
+    // A new emitter address starts its own sequence, so forget the last
+    // one received from the previous address.
+    if emitter.address != address {
+        emitter.last_sequence = None;
+    }
+    emitter.chain = chain;
+    emitter.address = address;
+    Ok(())
+}
+
+/// Posts an `Alive` message carrying this program's ID from its `emitter`,
+/// into the message account seeded by the emitter's next `sequence`. The
+/// Wormhole `fee` is paid by the payer of `accounts` first.
+fn post_alive<'info>(
+    wormhole_program: AccountInfo<'info>,
+    accounts: wormhole::PostMessage<'info>,
+    fee: u64,
+    sequence: u64,
+    message_bump: u8,
+    emitter: &WormholeEmitter,
+    config: &Config,
+) -> Result<()> {
+    // If Wormhole requires a fee before posting a message, we need to
+    // transfer lamports to the fee collector. Otherwise
+    // `wormhole::post_message` will fail.
+    if fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.clone(),
+                system_program::Transfer {
+                    from: accounts.payer.clone(),
+                    to: accounts.fee_collector.clone(),
+                },
+            ),
+            fee,
+        )?;
+    }
+
+    let payload = HelloWorldMessage::Alive {
+        version: ALIVE_VERSION,
+        program_id: crate::ID,
+    }
+    .try_to_vec()?;
+
+    wormhole::post_message(
+        CpiContext::new_with_signer(
+            wormhole_program,
+            accounts,
+            &[
+                &[
+                    SEED_PREFIX_SENT,
+                    &sequence.to_le_bytes()[..],
+                    &[message_bump],
+                ],
+                &[wormhole::SEED_PREFIX_EMITTER, &[emitter.bump]],
+            ],
+        ),
+        config.batch_id,
+        payload,
+        config.finality.try_into()?,
+    )
+}
+
+/// Whether `mint` is the wrapped SOL mint, whose token accounts hold their
+/// balance as lamports.
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == native_mint::ID
+}
+
+/// Scales `amount` from `from_decimals` to `to_decimals`. Scaling down
+/// truncates.
+pub fn normalize_amount(amount: u64, from_decimals: u8, to_decimals: u8) -> Result<u64> {
+    if to_decimals >= from_decimals {
+        10u64
+            .checked_pow((to_decimals - from_decimals).into())
+            .and_then(|scale| amount.checked_mul(scale))
+            .ok_or_else(|| CustomError::AmountOverflow.into())
+    } else {
+        // Any amount scales down to zero past the largest power of ten.
+        Ok(10u64
+            .checked_pow((from_decimals - to_decimals).into())
+            .map_or(0, |scale| amount / scale))
+    }
+}
+
+/// Leaf of the Merkle tree read by [`claim_with_proof`](claim_token::claim_with_proof):
+/// `keccak256(user || amount)`, with `amount` big-endian.
+pub fn merkle_leaf(user: &Pubkey, amount: u64) -> [u8; 32] {
+    keccak::hashv(&[user.as_ref(), &amount.to_be_bytes()]).0
+}
+
+/// Checks that `proof` leads from `leaf` to `root`. Each pair of nodes is
+/// hashed in ascending order, so the proof carries no left/right flags.
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: &[u8; 32]) -> bool {
+    let computed = proof.iter().fold(*leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).0
+        } else {
+            keccak::hashv(&[sibling, &node]).0
+        }
+    });
+    computed == *root
+}
+
+/// Transfers `amount` of the allocation of `accounts.user` from the vault
+/// into its destination, deducting it from the remaining allocation.
+fn transfer_claim(accounts: &mut ClaimToken, amount: u64, memo: [u8; 32]) -> Result<ClaimResult> {
+    let to = match &accounts.recipient {
+        Some(recipient) => recipient.to_account_info(),
+        None => {
+            require!(!has_recipient(accounts), CustomError::RecipientMismatch);
+            accounts.user.to_account_info()
+        }
+    };
+    transfer_claim_to(accounts, to, amount, memo)
+}
+
+/// Convenience method to check whether the claimed allocation is paid into a
+/// recipient instead of `accounts.user`.
+fn has_recipient(accounts: &ClaimToken) -> bool {
+    accounts
+        .user_state
+        .as_ref()
+        .is_some_and(|user_state| user_state.recipient != Pubkey::default())
+}
+
+/// Like [`transfer_claim`], paying into the `to` token account instead of
+/// `accounts.user`.
+fn transfer_claim_to<'info>(
+    accounts: &mut ClaimToken<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+    memo: [u8; 32],
+) -> Result<ClaimResult> {
+    check_claim(accounts, amount)?;
+    // Debiting before the transfer is safe: a failed transfer fails the whole
+    // transaction, which rolls the debit back with it. The token program can't
+    // call back into this program, so there is no reentrancy either.
+    let user_state = accounts
+        .user_state
+        .as_mut()
+        .ok_or(CustomError::Unauthorized)?;
+    accounts.state.debit(user_state, amount)?;
+    let remaining = user_state.amount;
+    let amount_transferred = pay_claim(accounts, to, amount, memo)?;
+
+    Ok(ClaimResult {
+        amount_transferred,
+        remaining,
+    })
+}
+
+/// Wallet that signed the claim: `accounts.delegate` if present, otherwise
+/// `accounts.claimer`.
+fn claim_signer(accounts: &ClaimToken) -> Pubkey {
+    accounts
+        .delegate
+        .as_ref()
+        .map_or(accounts.claimer.key(), |delegate| delegate.key())
+}
+
+/// Checks the state's claim requirements for a claim of `amount`, recording it
+/// against the claimer's KYC tier when required.
+fn check_claim(accounts: &mut ClaimToken, amount: u64) -> Result<()> {
+    require!(
+        accounts
+            .state
+            .is_funded_claimer(accounts.claimer.lamports()),
+        CustomError::InsufficientSol
+    );
+    let now = Clock::get()?.unix_timestamp;
+    require!(!accounts.state.is_expired(now), CustomError::ClaimExpired);
+    require!(
+        !accounts
+            .user_state
+            .as_ref()
+            .is_some_and(|user_state| user_state.is_expired(now)),
+        CustomError::ClaimExpired
+    );
+    require!(
+        !accounts
+            .user_state
+            .as_ref()
+            .is_some_and(|user_state| user_state.pending_review),
+        CustomError::PendingReview
+    );
+    require!(
+        accounts
+            .state
+            .is_destination_allowed(accounts.destination_registry.is_some()),
+        CustomError::DestinationNotRegistered
+    );
+    require!(
+        accounts.state.meets_min_claim(amount),
+        CustomError::BelowMinimum
+    );
+    if accounts.state.require_kyc {
+        // Wallets without a tier can't claim anything.
+        accounts
+            .kyc_tier
+            .as_mut()
+            .ok_or(CustomError::KycLimitExceeded)?
+            .record_claim(amount)?;
+    }
+    Ok(())
+}
+
+/// Transfers `amount` from the vault to the `to` token account, minus the
+/// protocol fee and the referrer's cut. Returns the amount the user received.
+fn pay_claim<'info>(
+    accounts: &mut ClaimToken<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+    memo: [u8; 32],
+) -> Result<u64> {
+    require!(
+        accounts.state.accepts_memo(&memo),
+        CustomError::MemoRequired
+    );
+    accounts.state.record_claim(amount)?;
+
+    // Claims without an allocation get no discount, nor correlation id, nor
+    // referrer.
+    let (fee_discount_bps, correlation_id, referrer) =
+        accounts
+            .user_state
+            .as_ref()
+            .map_or((0, NO_CORRELATION_ID, None), |user_state| {
+                (
+                    user_state.fee_discount_bps,
+                    user_state.correlation_id,
+                    user_state.referrer_of(&accounts.user.owner),
+                )
+            });
+    let (fee, amount) = accounts.state.split_fee(amount, fee_discount_bps);
+    let (referral, amount) = match referrer {
+        Some(_) => accounts.state.split_referral(amount),
+        None => (0, amount),
+    };
+    let vault_bump = accounts.state.vault_bump;
+    transfer_fee(
+        &accounts.vault,
+        &accounts.fee_account,
+        &accounts.mint,
+        &accounts.vault_authority,
+        &accounts.token_program,
+        vault_bump,
+        fee,
+    )?;
+    if referral > 0 {
+        let referrer = accounts
+            .referrer
+            .as_ref()
+            .ok_or(CustomError::ReferrerMismatch)?;
+        transfer_from_vault(
+            &accounts.vault,
+            referrer.to_account_info(),
+            &accounts.mint,
+            &accounts.vault_authority,
+            &accounts.token_program,
+            vault_bump,
+            referral,
+        )?;
+    }
+    let recipient = to.key();
+    transfer_from_vault(
+        &accounts.vault,
+        to,
+        &accounts.mint,
+        &accounts.vault_authority,
+        &accounts.token_program,
+        vault_bump,
+        amount,
+    )?;
+
+    emit!(TokenClaimed {
+        user: accounts.user.owner,
+        amount,
+        recipient,
+        memo,
+        correlation_id,
+    });
+    Ok(amount)
+}
+
+/// Transfers the `fee` cut of a claim from `vault` to `fee_account`, which
+/// must be present when the fee is nonzero.
+fn transfer_fee<'info>(
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    fee_account: &Option<InterfaceAccount<'info, TokenAccount>>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault_authority: &UncheckedAccount<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    vault_bump: u8,
+    fee: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+    let fee_account = fee_account
+        .as_ref()
+        .ok_or(CustomError::InvalidFeeRecipient)?;
+
+    transfer_from_vault(
+        vault,
+        fee_account.to_account_info(),
+        mint,
+        vault_authority,
+        token_program,
+        vault_bump,
+        fee,
+    )
+}
+
+/// Transfers `amount` from `vault` to `to`, signed by the vault authority PDA.
+fn transfer_from_vault<'info>(
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault_authority: &UncheckedAccount<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = TransferChecked {
+        from: vault.to_account_info(),
+        mint: mint.to_account_info(),
+        to,
+        authority: vault_authority.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            cpi_program,
+            cpi_accounts,
+            &[&[SEED_PREFIX_VAULT, &[vault_bump]]],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
 #[derive(Accounts)]
 pub struct Initialize<'info> {
-    #[account(init, payer = admin, space = 8 + 32 + 8)]
+    #[account(
+        init,
+        payer = admin,
+        seeds = [State::SEED_PREFIX],
+        bump,
+        space = State::MAXIMUM_SIZE
+    )]
     pub state: Account<'info, State>,
     #[account(mut)]
     pub admin: Signer<'info>,
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()) @ CustomError::UnauthorizedInit
+    )]
+    /// This program. Used to find its program data account.
+    pub program: Program<'info, program::ClaimToken>,
+    #[account(
+        constraint = is_initializer(program_data.upgrade_authority_address, &admin.key()) @ CustomError::UnauthorizedInit
+    )]
+    /// Program data account of this program, holding its upgrade authority.
+    pub program_data: Account<'info, ProgramData>,
+    #[account(seeds = [SEED_PREFIX_VAULT], bump)]
+    /// CHECK: PDA owning the vault. Only its bump is saved.
+    pub vault_authority: UncheckedAccount<'info>,
     /// The system_program field stores the system program account.
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimToken<'info> {
+pub struct SetOperator<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        constraint = state.is_owner_or_operator(&authority.key()) @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    /// Owner or operator of the state.
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeOwner<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOwner<'info> {
+    #[account(
+        mut,
+        constraint = state.is_pending_owner(&pending_owner.key()) @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub pending_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEventLevel<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinClaimAmount<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetReviewThreshold<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveClaim<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [UserState::SEED_PREFIX, user_state.user.as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinClaimerSol<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetExpiryWarningSecs<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRateLimit<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRequiredConsistency<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PokeExpiry<'info> {
+    #[account(
+        mut,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct SetClaimDeadline<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetUserExpiry<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [UserState::SEED_PREFIX, user_state.user.as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+}
+
+#[derive(Accounts)]
+pub struct SetRecipient<'info> {
+    pub user: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = user.owner @ CustomError::InvalidUser)]
+    /// Wallet owning the `user` token account.
+    pub claimer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [UserState::SEED_PREFIX, user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeDiscount<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [UserState::SEED_PREFIX, user_state.user.as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+}
+
+#[derive(Accounts)]
+pub struct InjectTestAllocation<'info> {
+    #[account(mut)]
+    /// Pays for the user state account if it does not exist yet.
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    /// CHECK: User state account of the injected user. Its address is checked,
+    /// and the account created if needed, by the handler.
+    pub user_state: UncheckedAccount<'info>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepExpired<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    /// Receives the rent of the swept user state accounts.
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PurgeTestAllocations<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    /// Receives the rent of the purged user state accounts.
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRequireRegisteredDestination<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFee<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetReferralBps<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRequireKyc<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRequireMemo<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMerkleRoot<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct SetKycTier<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [KycTier::SEED_PREFIX, wallet.as_ref()],
+        bump,
+        space = KycTier::MAXIMUM_SIZE
+    )]
+    pub kyc_tier: Account<'info, KycTier>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(destination: Pubkey)]
+pub struct RegisterDestination<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [DestinationRegistry::SEED_PREFIX, destination.as_ref()],
+        bump,
+        space = DestinationRegistry::MAXIMUM_SIZE
+    )]
+    pub destination_registry: Account<'info, DestinationRegistry>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseState<'info> {
+    #[account(
+        mut,
+        has_one = owner @ CustomError::InvalidOwner,
+        close = owner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    /// Receives the rent of the state account.
+    pub owner: Signer<'info>,
+    #[account(seeds = [SEED_PREFIX_VAULT], bump = state.vault_bump)]
+    /// CHECK: PDA owning the vault.
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        address = state.allowed_mint @ CustomError::MintNotAllowed,
+        constraint = mint.to_account_info().owner == &token_program.key()
+            @ ErrorCode::ConstraintAssociatedTokenTokenProgram
+    )]
+    /// Allowed mint, owned by `token_program`.
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        address = get_associated_token_address_with_program_id(
+            &vault_authority.key(),
+            &mint.key(),
+            &token_program.key()
+        )
+    )]
+    /// Associated token account of `vault_authority` for the allowed mint.
+    /// Must be empty. Create it first if the vault was never funded.
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    /// Token program of the allowed mint.
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAllocation<'info> {
+    #[account(seeds = [State::SEED_PREFIX], bump)]
+    pub state: Account<'info, State>,
+    /// Destination token account the allocation is keyed by.
+    pub user: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        close = recipient,
+        seeds = [UserState::SEED_PREFIX, user.key().as_ref()],
+        bump,
+        constraint = user_state.amount == 0 @ CustomError::AllocationNotClaimed
+    )]
+    /// Fully claimed allocation of `user`, closed along with any dust left
+    /// below a unit. Afterwards the user is reported as never allocated.
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        constraint = authority.key() == state.owner
+            || authority.key() == user.owner @ CustomError::InvalidOwner
+    )]
+    /// Either the owner or the wallet owning `user`.
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    /// CHECK: Receives the rent of the user state account.
+    pub recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct Deny<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [DenyEntry::SEED_PREFIX, wallet.as_ref()],
+        bump,
+        space = DenyEntry::MAXIMUM_SIZE
+    )]
+    pub deny_entry: Account<'info, DenyEntry>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct Allow<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [DenyEntry::SEED_PREFIX, wallet.as_ref()],
+        bump
+    )]
+    pub deny_entry: Account<'info, DenyEntry>,
+}
+
+#[derive(Accounts)]
+#[instruction(destination: Pubkey)]
+pub struct DeregisterDestination<'info> {
+    #[account(
+        has_one = owner @ CustomError::InvalidOwner,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
     #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [DestinationRegistry::SEED_PREFIX, destination.as_ref()],
+        bump
+    )]
+    pub destination_registry: Account<'info, DestinationRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimToken<'info> {
+    #[account(
+        mut,
+        constraint = !state.paused @ CustomError::Paused,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
     pub state: Account<'info, State>,
     #[account(mut)]
     pub user: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [UserState::SEED_PREFIX, user.key().as_ref()],
+        bump,
+        constraint = user_state.mint == vault.mint @ CustomError::MintMismatch
+    )]
+    /// Allocation of `user`, denominated in the mint of `vault`. Only
+    /// [`claim_with_proof`](claim_token::claim_with_proof) claims, which are
+    /// never credited, may leave it out.
+    pub user_state: Option<Account<'info, UserState>>,
+    #[account(
+        address = user.owner @ CustomError::InvalidUser,
+        constraint = claimer.is_signer || delegate.is_some() @ ErrorCode::AccountNotSigner
+    )]
+    /// CHECK: Wallet owning the `user` token account. Signs the claim unless
+    /// `delegate` does. Its lamports are checked against
+    /// `state.min_claimer_sol`.
+    pub claimer: UncheckedAccount<'info>,
+    #[account(
+        constraint = user_state
+            .as_ref()
+            .is_some_and(|user_state| user_state.is_delegate(&delegate.key()))
+            @ CustomError::UnauthorizedDelegate
+    )]
+    /// Delegate of the allocation, signing the claim on the owner's behalf.
+    /// The tokens still go to `user`.
+    pub delegate: Option<Signer<'info>>,
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &vault_authority.key(),
+            &state.allowed_mint,
+            &token_program.key()
+        ),
+        constraint = vault.owner == vault_authority.key() @ CustomError::InvalidOwner,
+        constraint = state.is_mint_allowed(&vault.mint) @ CustomError::MintNotAllowed,
+        constraint = vault.mint == user.mint @ CustomError::MintMismatch
+    )]
+    /// Associated token account of `vault_authority` holding the claimable
+    /// tokens, funded by [`fund_vault`](claim_token::fund_vault).
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(seeds = [SEED_PREFIX_VAULT], bump = state.vault_bump)]
+    /// CHECK: PDA owning the `vault` token account. Signs the transfer.
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(address = vault.mint @ CustomError::MintMismatch)]
+    /// Mint of the `vault` token account. Its decimals are checked by the
+    /// transfer.
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [
+            DestinationRegistry::SEED_PREFIX,
+            user_state
+                .as_ref()
+                .map_or(user.key(), |user_state| user_state.destination())
+                .as_ref()
+        ],
+        bump
+    )]
+    /// Registry entry approving the token account the claim is paid into,
+    /// `user` or the allocation's recipient, as a destination. Only required
+    /// when `state.require_registered_destination` is set.
+    pub destination_registry: Option<Account<'info, DestinationRegistry>>,
+    #[account(
+        mut,
+        seeds = [KycTier::SEED_PREFIX, claimer.key().as_ref()],
+        bump
+    )]
+    /// KYC tier of `claimer`. Only required when `state.require_kyc` is set.
+    pub kyc_tier: Option<Account<'info, KycTier>>,
+    #[account(
+        seeds = [DenyEntry::SEED_PREFIX, claimer.key().as_ref()],
+        bump,
+        constraint = deny_entry.data_is_empty() @ CustomError::Denied
+    )]
+    /// CHECK: Deny entry address of `claimer`. Must not exist.
+    pub deny_entry: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = state.fee_recipient @ CustomError::InvalidFeeRecipient,
+        constraint = fee_account.mint == vault.mint @ CustomError::MintMismatch
+    )]
+    /// Token account receiving the protocol fee. Only required when
+    /// `state.fee_bps` is nonzero.
+    pub fee_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_state
+            .as_ref()
+            .is_some_and(|user_state| user_state.recipient == recipient.key())
+            @ CustomError::RecipientMismatch,
+        constraint = recipient.mint == vault.mint @ CustomError::MintMismatch
+    )]
+    /// Recipient the allocation's claims are paid into instead of `user`.
+    /// Required when the allocation names one.
+    pub recipient: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_state
+            .as_ref()
+            .is_some_and(|user_state| user_state.referrer == referrer.owner)
+            @ CustomError::ReferrerMismatch,
+        constraint = referrer.mint == vault.mint @ CustomError::MintMismatch
+    )]
+    /// Token account of the wallet that referred the allocation's user, paid
+    /// the state's `referral_bps` cut. Required when the cut is nonzero,
+    /// unless the user referred themselves.
+    pub referrer: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// The token_program field stores the token program account.
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateRecipient<'info> {
     #[account(mut)]
-    pub owner: InterfaceAccount<'info, TokenAccount>,
+    /// Pays for the recipient token account if it does not exist yet.
+    pub payer: Signer<'info>,
+    /// CHECK: Wallet that owns the recipient token account.
+    pub wallet: UncheckedAccount<'info>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = wallet
+    )]
+    /// Associated token account of `wallet` for `mint`.
+    pub recipient: InterfaceAccount<'info, TokenAccount>,
     /// The token_program field stores the token program account.
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimNative<'info> {
+    /// `claim.user` names the allocation claimed. It receives nothing.
+    pub claim: ClaimToken<'info>,
+    #[account(
+        mut,
+        address = claim.user.owner @ CustomError::InvalidUser,
+        constraint = is_native_mint(&claim.user.mint) @ CustomError::NotNativeMint
+    )]
+    /// Wallet owning `claim.user`. Pays for `native`, signs its closing and
+    /// receives its lamports.
+    pub wallet: Signer<'info>,
+    #[account(address = claim.mint.key() @ CustomError::MintMismatch)]
+    /// Native mint, the same account as `claim.mint`.
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = wallet,
+        seeds = [SEED_PREFIX_NATIVE, wallet.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = wallet
+    )]
+    /// Temporary wrapped SOL account the claim is paid into. Closed by the
+    /// claim.
+    pub native: InterfaceAccount<'info, TokenAccount>,
+    /// Token program of the native mint.
     pub token_program: Program<'info, Token>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTokenToNewAccount<'info> {
+    /// Created before `claim` is loaded, so `claim.user` may point at an
+    /// account that did not exist when the transaction was built.
+    pub recipient: CreateRecipient<'info>,
+    /// `claim.user` must be `recipient.recipient`.
+    pub claim: ClaimToken<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWithProof<'info> {
+    pub claim: ClaimToken<'info>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [
+            ClaimedLeaf::SEED_PREFIX,
+            claim.state.merkle_root.as_ref(),
+            claim.user.key().as_ref()
+        ],
+        bump,
+        space = ClaimedLeaf::MAXIMUM_SIZE
+    )]
+    /// Created by the claim, so a replayed leaf fails.
+    pub claimed_leaf: Account<'info, ClaimedLeaf>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(idempotency_key: [u8; 32])]
+pub struct ClaimTokenIdempotent<'info> {
+    pub claim: ClaimToken<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [
+            ClaimReceipt::SEED_PREFIX,
+            claim.user.key().as_ref(),
+            claim_signer(&claim).as_ref(),
+            &idempotency_key
+        ],
+        bump,
+        space = ClaimReceipt::MAXIMUM_SIZE
+    )]
+    /// Receipt of the claims signed with `idempotency_key`, by
+    /// `claim.delegate` if present and `claim.claimer` otherwise. Keyed by the
+    /// signer too, so another signer reusing the key, such as a later owner of
+    /// `claim.user`, doesn't inherit its receipts.
+    pub receipt: Account<'info, ClaimReceipt>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BatchClaim<'info> {
+    #[account(
+        mut,
+        constraint = !state.paused @ CustomError::Paused,
+        seeds = [State::SEED_PREFIX],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &vault_authority.key(),
+            &state.allowed_mint,
+            &token_program.key()
+        ),
+        constraint = vault.owner == vault_authority.key() @ CustomError::InvalidOwner,
+        constraint = state.is_mint_allowed(&vault.mint) @ CustomError::MintNotAllowed
+    )]
+    /// Associated token account of `vault_authority` holding the claimable
+    /// tokens.
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(seeds = [SEED_PREFIX_VAULT], bump = state.vault_bump)]
+    /// CHECK: PDA owning the `vault` token account. Signs the transfers.
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(address = vault.mint @ CustomError::MintMismatch)]
+    /// Mint of the `vault` token account. Its decimals are checked by the
+    /// transfers.
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        address = state.fee_recipient @ CustomError::InvalidFeeRecipient,
+        constraint = fee_account.mint == vault.mint @ CustomError::MintMismatch
+    )]
+    /// Token account receiving the protocol fee. Only required when
+    /// `state.fee_bps` is nonzero.
+    pub fee_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// The token_program field stores the token program account.
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct FundVault<'info> {
+    #[account(seeds = [State::SEED_PREFIX], bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    /// Authority of `source`. Pays for the vault if it does not exist yet.
+    pub depositor: Signer<'info>,
+    #[account(mut)]
+    pub source: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = state.allowed_mint @ CustomError::MintNotAllowed)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(seeds = [SEED_PREFIX_VAULT], bump = state.vault_bump)]
+    /// CHECK: PDA owning the vault.
+    pub vault_authority: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority
+    )]
+    /// Associated token account of `vault_authority` for `mint`.
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    /// The token_program field stores the token program account.
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// The system_program field stores the system program account.
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetClaimable<'info> {
+    #[account(seeds = [State::SEED_PREFIX], bump)]
+    pub state: Account<'info, State>,
+    pub user: InterfaceAccount<'info, TokenAccount>,
+    #[account(seeds = [UserState::SEED_PREFIX, user.key().as_ref()], bump)]
+    /// CHECK: User state account of `user`. May not exist yet.
+    pub user_state: UncheckedAccount<'info>,
+    #[account(address = user.owner @ CustomError::InvalidUser)]
+    pub claimer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ViewClaim<'info> {
+    #[account(seeds = [State::SEED_PREFIX], bump)]
+    pub state: Account<'info, State>,
+    /// CHECK: Destination token account the allocation is keyed by. Only its
+    /// key is read.
+    pub user: UncheckedAccount<'info>,
+    #[account(seeds = [UserState::SEED_PREFIX, user.key().as_ref()], bump)]
+    /// CHECK: User state account of `user`. May not exist yet.
+    pub user_state: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ViewUsers<'info> {
+    #[account(seeds = [State::SEED_PREFIX], bump)]
+    pub state: Account<'info, State>,
 }
 
 #[account]
+#[derive(Default)]
 pub struct State {
     pub owner: Pubkey,
-    pub users: HashMap<Pubkey, u64>,
+    /// Blocks [`claim_token`](claim_token::claim_token) and
+    /// [`receive_message`](claim_token::receive_message) while set.
+    pub paused: bool,
+    /// Owner proposed by [`propose_owner`](claim_token::propose_owner), waiting
+    /// to sign [`accept_owner`](claim_token::accept_owner).
+    pub pending_owner: Option<Pubkey>,
+    /// Lamports the wallet owning the destination token account must hold to
+    /// claim. Zero disables the check.
+    pub min_claimer_sol: u64,
+    /// Only lets claims through to destinations with a
+    /// [`DestinationRegistry`] entry while set.
+    pub require_registered_destination: bool,
+    /// Unix timestamp after which allocations can no longer be claimed and
+    /// the owner may sweep them. Zero disables the deadline.
+    pub claim_deadline: i64,
+    /// Events emitted by [`batch_claim`](claim_token::batch_claim). See the
+    /// `EVENT_LEVEL_*` constants.
+    pub event_level: u8,
+    /// Only mint the owner token account may pay claims in. Set once by
+    /// [`initialize`](claim_token::initialize).
+    pub allowed_mint: Pubkey,
+    /// Seconds before `claim_deadline` from which
+    /// [`poke_expiry`](claim_token::poke_expiry) emits an [`ExpiryWarning`].
+    /// Zero disables the warning.
+    pub expiry_warning_secs: i64,
+    /// `claim_deadline` the last [`ExpiryWarning`] was emitted for.
+    pub expiry_warned_deadline: i64,
+    /// Smallest amount a claim may transfer. Zero disables the check.
+    pub min_claim_amount: u64,
+    /// Caps each claimer's cumulative claims by its [`KycTier`] while set.
+    pub require_kyc: bool,
+    /// Cut of every claim, in basis points, paid to `fee_recipient`.
+    pub fee_bps: u16,
+    /// Token account receiving the protocol fee.
+    pub fee_recipient: Pubkey,
+    /// Sum of all claimed allocations, protocol fees included.
+    pub total_claimed: u64,
+    /// Number of claims paid, counting each user of a batch.
+    pub claim_count: u64,
+    /// Root of the Merkle tree of `(user, amount)` leaves claimable with
+    /// [`claim_with_proof`](claim_token::claim_with_proof). Zero disables
+    /// proof claims.
+    pub merkle_root: [u8; 32],
+    /// May pause the program and register emitters besides the owner, but
+    /// can't change ownership or fees. The default pubkey means none.
+    pub operator: Pubkey,
+    /// Sum of all allocations credited by
+    /// [`receive_message`](claim_token::receive_message), in the mint's
+    /// decimals. Bounds `total_claimed`, except for
+    /// [`claim_with_proof`](claim_token::claim_with_proof) claims, which are
+    /// never credited.
+    pub total_received: u64,
+    /// Bump of the [`SEED_PREFIX_VAULT`] PDA owning the vault.
+    pub vault_bump: u8,
+    /// Length in seconds of a rate limit window.
+    pub window_secs: i64,
+    /// Most [`receive_message`](claim_token::receive_message) may credit
+    /// within a window. Zero disables the rate limit.
+    pub window_limit: u64,
+    /// Unix timestamp at which the current window started.
+    pub window_start: i64,
+    /// Amount credited within the current window.
+    pub window_credited: u64,
+    /// Lowest consistency level at which messages accepted by
+    /// [`receive_message`](claim_token::receive_message) may have been
+    /// emitted.
+    pub required_consistency: u8,
+    /// Whether claims must carry a nonzero memo, which only
+    /// [`claim_with_memo`](claim_token::claim_with_memo) can attach.
+    pub require_memo: bool,
+    /// Sum of the allocations left to claim across all [`UserState`]
+    /// accounts.
+    pub outstanding: u64,
+    /// Slot at which the last Alive heartbeat of this program was received.
+    pub last_alive_slot: u64,
+    /// Minimum lamports a relayer must have locked in its [`RelayerStake`] to
+    /// call [`receive_message`](claim_token::receive_message). Zero disables
+    /// the check.
+    pub min_relayer_stake: u64,
+    /// Allocations credited above this amount can't be claimed until the
+    /// owner approves them. Zero disables reviews.
+    pub review_threshold: u64,
+    /// Basis points of every referred claim, after the protocol fee, paid to
+    /// the allocation's referrer.
+    pub referral_bps: u16,
 }
 
-#[error_code]
-pub enum CustomError {
-    #[msg("User not found")]
+impl State {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 32 // owner
+        + 1 // paused
+        + 1 + 32 // pending_owner
+        + 8 // min_claimer_sol
+        + 1 // require_registered_destination
+        + 8 // claim_deadline
+        + 1 // event_level
+        + 32 // allowed_mint
+        + 8 // expiry_warning_secs
+        + 8 // expiry_warned_deadline
+        + 8 // min_claim_amount
+        + 1 // require_kyc
+        + 2 // fee_bps
+        + 32 // fee_recipient
+        + 8 // total_claimed
+        + 8 // claim_count
+        + 32 // merkle_root
+        + 32 // operator
+        + 8 // total_received
+        + 1 // vault_bump
+        + 8 // window_secs
+        + 8 // window_limit
+        + 8 // window_start
+        + 8 // window_credited
+        + 1 // required_consistency
+        + 1 // require_memo
+        + 8 // outstanding
+        + 8 // last_alive_slot
+        + 8 // min_relayer_stake
+        + 8 // review_threshold
+        + 2 // referral_bps
+    ;
+    /// AKA `b"state"`.
+    pub const SEED_PREFIX: &'static [u8; 5] = b"state";
+
+    /// One [`TokenClaimed`] event per user paid.
+    pub const EVENT_LEVEL_CLAIM: u8 = 0;
+    /// A single [`BatchClaimEvent`] per batch.
+    pub const EVENT_LEVEL_BATCH: u8 = 1;
+
+    /// Convenience method to check whether `key` is the owner or the operator.
+    pub fn is_owner_or_operator(&self, key: &Pubkey) -> bool {
+        *key == self.owner || (self.operator != Pubkey::default() && *key == self.operator)
+    }
+
+    /// Convenience method to check whether `key` is the owner proposed by
+    /// [`propose_owner`](claim_token::propose_owner).
+    pub fn is_pending_owner(&self, key: &Pubkey) -> bool {
+        self.pending_owner == Some(*key)
+    }
+
+    /// Convenience method to check whether a claimer holding `lamports`
+    /// satisfies `min_claimer_sol`.
+    pub fn is_funded_claimer(&self, lamports: u64) -> bool {
+        lamports >= self.min_claimer_sol
+    }
+
+    /// Splits a claim of `amount` into the protocol fee and what the user
+    /// receives. The fee rounds down, after taking `fee_discount_bps` off
+    /// `fee_bps`. Discounts larger than the fee waive it.
+    pub fn split_fee(&self, amount: u64, fee_discount_bps: u16) -> (u64, u64) {
+        let fee_bps = self.fee_bps.saturating_sub(fee_discount_bps);
+        let fee = (u128::from(amount) * u128::from(fee_bps) / u128::from(MAX_FEE_BPS)) as u64;
+        (fee, amount - fee)
+    }
+
+    /// Splits what the user receives of a claim, `amount`, into the
+    /// referrer's `referral_bps` cut and the rest. The cut rounds down.
+    pub fn split_referral(&self, amount: u64) -> (u64, u64) {
+        let referral =
+            (u128::from(amount) * u128::from(self.referral_bps) / u128::from(MAX_FEE_BPS)) as u64;
+        (referral, amount - referral)
+    }
+
+    /// Convenience method to check whether a claim carrying `memo` satisfies
+    /// `require_memo`.
+    pub fn accepts_memo(&self, memo: &[u8; 32]) -> bool {
+        !self.require_memo || *memo != NO_MEMO
+    }
+
+    /// Convenience method to check whether a claim of `amount` satisfies
+    /// `min_claim_amount`.
+    pub fn meets_min_claim(&self, amount: u64) -> bool {
+        amount >= self.min_claim_amount
+    }
+
+    /// Convenience method to check whether an allocation of `amount` exceeds
+    /// `review_threshold`.
+    pub fn needs_review(&self, amount: u64) -> bool {
+        self.review_threshold != 0 && amount > self.review_threshold
+    }
+
+    /// Convenience method to check whether a destination may receive claims,
+    /// given whether it has a [`DestinationRegistry`] entry.
+    pub fn is_destination_allowed(&self, registered: bool) -> bool {
+        registered || !self.require_registered_destination
+    }
+
+    /// Convenience method to check whether claims may be paid in `mint`.
+    pub fn is_mint_allowed(&self, mint: &Pubkey) -> bool {
+        self.allowed_mint == *mint
+    }
+
+    /// Convenience method to check whether a message emitted at consistency
+    /// level `finality` may be received.
+    pub fn meets_consistency(&self, finality: u8) -> bool {
+        finality >= self.required_consistency
+    }
+
+    /// Convenience method to check whether the claim deadline has passed at
+    /// unix timestamp `now`.
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.claim_deadline != 0 && now > self.claim_deadline
+    }
+
+    /// Convenience method to check whether an [`ExpiryWarning`] is due at unix
+    /// timestamp `now`: the deadline is within `expiry_warning_secs` and has
+    /// not been warned about yet.
+    pub fn should_warn_expiry(&self, now: i64) -> bool {
+        self.claim_deadline != 0
+            && self.expiry_warning_secs > 0
+            && self.expiry_warned_deadline != self.claim_deadline
+            && !self.is_expired(now)
+            && now >= self.claim_deadline.saturating_sub(self.expiry_warning_secs)
+    }
+
+    /// Counts `amount` credited at unix timestamp `now` against the rate
+    /// limit, starting a new window once the current one has elapsed.
+    pub fn record_window_credit(&mut self, amount: u64, now: i64) -> Result<()> {
+        if self.window_limit == 0 {
+            return Ok(());
+        }
+        if now >= self.window_start.saturating_add(self.window_secs) {
+            self.window_start = now;
+            self.window_credited = 0;
+        }
+
+        let window_credited = self
+            .window_credited
+            .checked_add(amount)
+            .filter(|credited| *credited <= self.window_limit)
+            .ok_or(CustomError::RateLimited)?;
+        self.window_credited = window_credited;
+        Ok(())
+    }
+
+    /// Credits `user_state` with `amount` expressed with `from_decimals`
+    /// decimals, scaled to `to_decimals`, and counts it in `total_received`
+    /// and `outstanding`. Holds the allocation for review if it ends up above
+    /// `review_threshold`. Returns the whole units credited. Nothing changes on
+    /// error.
+    pub fn credit(
+        &mut self,
+        user_state: &mut UserState,
+        amount: u64,
+        from_decimals: u8,
+        to_decimals: u8,
+    ) -> Result<u64> {
+        let mut credited_state = user_state.clone();
+        let credited = credited_state.credit(amount, from_decimals, to_decimals)?;
+        if self.needs_review(credited_state.amount) {
+            credited_state.pending_review = true;
+        }
+        let total_received = self
+            .total_received
+            .checked_add(credited)
+            .ok_or(CustomError::AmountOverflow)?;
+        let outstanding = self
+            .outstanding
+            .checked_add(credited)
+            .ok_or(CustomError::AmountOverflow)?;
+
+        *user_state = credited_state;
+        self.total_received = total_received;
+        self.outstanding = outstanding;
+        Ok(credited)
+    }
+
+    /// Deducts `amount` from the allocation in `user_state`, see
+    /// [`UserState::debit`], and from `outstanding`.
+    pub fn debit(&mut self, user_state: &mut UserState, amount: u64) -> Result<()> {
+        user_state.debit(amount)?;
+        self.outstanding = self.outstanding.saturating_sub(amount);
+        Ok(())
+    }
+
+    /// Adds a claim of `amount` to `total_claimed` and `claim_count`.
+    pub fn record_claim(&mut self, amount: u64) -> Result<()> {
+        self.total_claimed = self
+            .total_claimed
+            .checked_add(amount)
+            .ok_or(CustomError::AmountOverflow)?;
+        self.claim_count = self
+            .claim_count
+            .checked_add(1)
+            .ok_or(CustomError::AmountOverflow)?;
+        Ok(())
+    }
+
+    /// Convenience method to check whether any user has an allocation left to
+    /// claim.
+    pub fn has_pending_claims(&self) -> bool {
+        self.outstanding != 0
+    }
+
+    /// Records an Alive heartbeat received at `slot`. The heartbeat must come
+    /// from a deployment of this program.
+    pub fn record_alive(&mut self, program_id: &Pubkey, slot: u64) -> Result<()> {
+        require_keys_eq!(*program_id, crate::ID, HelloWorldError::InvalidMessage);
+        self.last_alive_slot = slot;
+        Ok(())
+    }
+}
+
+/// Packs a `(user, amount)` entry for every user state in `user_states`. Each
+/// entry is the user's pubkey followed by the amount in little-endian.
+pub fn pack_user_amounts<'a>(user_states: impl IntoIterator<Item = &'a UserState>) -> Vec<u8> {
+    let mut packed = Vec::new();
+    for user_state in user_states {
+        packed.extend_from_slice(user_state.user.as_ref());
+        packed.extend_from_slice(&user_state.amount.to_le_bytes());
+    }
+    packed
+}
+
+/// Loads the [`UserState`] account at `info`, or `None` if it was never
+/// created.
+fn read_user_state(info: &AccountInfo) -> Result<Option<UserState>> {
+    if info.data_is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Account::<UserState>::try_from(info)?.into_inner()))
+}
+
+/// Loads the [`UserState`] account of `user` at `info`, creating it in `mint`
+/// with `payer`'s lamports if it doesn't exist yet.
+fn load_user_state<'info>(
+    info: &AccountInfo<'info>,
+    user: &Pubkey,
+    mint: &Pubkey,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<Account<'info, UserState>> {
+    let (address, bump) = UserState::address(user);
+    require_keys_eq!(info.key(), address, CustomError::InvalidRemainingAccounts);
+
+    if info.data_is_empty() {
+        let signer_seeds: &[&[u8]] = &[UserState::SEED_PREFIX, user.as_ref(), &[bump]];
+        let space = UserState::MAXIMUM_SIZE as u64;
+        let rent = Rent::get()?.minimum_balance(UserState::MAXIMUM_SIZE);
+        let lamports = info.lamports();
+        if lamports == 0 {
+            system_program::create_account(
+                CpiContext::new_with_signer(
+                    system_program.to_account_info(),
+                    system_program::CreateAccount {
+                        from: payer.to_account_info(),
+                        to: info.clone(),
+                    },
+                    &[signer_seeds],
+                ),
+                rent,
+                space,
+                &crate::ID,
+            )?;
+        } else {
+            // Anyone may fund the address beforehand, which create_account
+            // refuses.
+            if lamports < rent {
+                system_program::transfer(
+                    CpiContext::new(
+                        system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: payer.to_account_info(),
+                            to: info.clone(),
+                        },
+                    ),
+                    rent - lamports,
+                )?;
+            }
+            system_program::allocate(
+                CpiContext::new_with_signer(
+                    system_program.to_account_info(),
+                    system_program::Allocate {
+                        account_to_allocate: info.clone(),
+                    },
+                    &[signer_seeds],
+                ),
+                space,
+            )?;
+            system_program::assign(
+                CpiContext::new_with_signer(
+                    system_program.to_account_info(),
+                    system_program::Assign {
+                        account_to_assign: info.clone(),
+                    },
+                    &[signer_seeds],
+                ),
+                &crate::ID,
+            )?;
+        }
+
+        let user_state = UserState {
+            user: *user,
+            mint: *mint,
+            ..Default::default()
+        };
+        user_state.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    }
+    Account::try_from(info)
+}
+
+/// Returned by [`claim_token`](claim_token::claim_token) and
+/// [`claim_token_to_new_account`](claim_token::claim_token_to_new_account).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClaimResult {
+    /// Amount transferred to the user, after the protocol fee.
+    pub amount_transferred: u64,
+    /// Allocation left to claim afterwards.
+    pub remaining: u64,
+}
+
+/// Returned by [`view_claim`](claim_token::view_claim).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClaimView {
+    /// Destination token account the allocation is keyed by.
+    pub user: Pubkey,
+    /// Allocation left to claim.
+    pub amount: u64,
+    /// Whether the user was allocated tokens and claimed all of them.
+    pub claimed: bool,
+}
+
+impl ClaimView {
+    /// Claim status of `user`, given its user state account if it exists.
+    pub fn new(user: Pubkey, user_state: Option<&UserState>) -> Self {
+        Self {
+            user,
+            amount: user_state.map_or(0, |user_state| user_state.amount),
+            claimed: user_state.is_some_and(UserState::is_claimed),
+        }
+    }
+}
+
+#[error_code]
+pub enum CustomError {
+    #[msg("User not found")]
     Unauthorized,
     #[msg("Invalid owner")]
     InvalidOwner,
+    #[msg("Too many accounts")]
+    TooManyAccounts,
+    #[msg("Program is paused")]
+    Paused,
+    #[msg("Mint mismatch")]
+    MintMismatch,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Insufficient SOL")]
+    InsufficientSol,
+    #[msg("Invalid remaining accounts")]
+    InvalidRemainingAccounts,
+    #[msg("Destination not registered")]
+    DestinationNotRegistered,
+    #[msg("Claim expired")]
+    ClaimExpired,
+    #[msg("Claim not expired")]
+    ClaimNotExpired,
+    #[msg("Already claimed")]
+    AlreadyClaimed,
+    #[msg("Recipient mismatch")]
+    RecipientMismatch,
+    #[msg("Amount overflow")]
+    AmountOverflow,
+    #[msg("Invalid event level")]
+    InvalidEventLevel,
+    #[msg("Mint not allowed")]
+    MintNotAllowed,
+    #[msg("Below minimum claim amount")]
+    BelowMinimum,
+    #[msg("KYC limit exceeded")]
+    KycLimitExceeded,
+    #[msg("Invalid fee")]
+    InvalidFee,
+    #[msg("Invalid fee recipient")]
+    InvalidFeeRecipient,
+    #[msg("Unauthorized initialization")]
+    UnauthorizedInit,
+    #[msg("Allocation not fully claimed")]
+    AllocationNotClaimed,
+    #[msg("Signer does not own the token account")]
+    InvalidUser,
+    #[msg("Invalid Merkle proof")]
+    InvalidProof,
+    #[msg("Rate limit exceeded")]
+    RateLimited,
+    #[msg("Wallet is denied")]
+    Denied,
+    #[msg("Claim memo required")]
+    MemoRequired,
+    #[msg("Vault still holds tokens")]
+    VaultNotEmpty,
+    #[msg("Mint is not wrapped SOL")]
+    NotNativeMint,
+    #[msg("Signer is not the allocation's delegate")]
+    UnauthorizedDelegate,
+    #[msg("Allocation is a test allocation")]
+    TestAllocation,
+    #[msg("Allocation is not a test allocation")]
+    NotTestAllocation,
+    #[msg("Claim pending review")]
+    PendingReview,
+    #[msg("Referrer mismatch")]
+    ReferrerMismatch,
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use anchor_lang::solana_program::entrypoint::ProgramResult;
+    use anchor_lang::solana_program::entrypoint::{MAX_PERMITTED_DATA_INCREASE, SUCCESS};
+    use anchor_lang::solana_program::instruction::Instruction;
+    use anchor_lang::solana_program::program_option::COption;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_lang::solana_program::program_stubs::{self, SyscallStubs};
+    use anchor_lang::solana_program::program_utils::limited_deserialize;
+    use anchor_lang::solana_program::system_instruction::{SystemError, SystemInstruction};
+    use anchor_lang::solana_program::sysvar;
+    use anchor_lang::{Ids, InstructionData};
+    use anchor_spl::token::spl_token;
+    use anchor_spl::token_2022::spl_token_2022;
+    use std::cell::{Cell, RefCell};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::mem::size_of;
+    use std::sync::Once;
+
+    #[test]
+    fn test_state() -> Result<()> {
+        assert_eq!(
+            State::MAXIMUM_SIZE,
+            size_of::<u64>()
+                + size_of::<Pubkey>()
+                + size_of::<bool>()
+                + size_of::<u8>()
+                + size_of::<Pubkey>()
+                + size_of::<u64>()
+                + size_of::<bool>()
+                + size_of::<i64>()
+                + size_of::<u8>()
+                + size_of::<Pubkey>()
+                + size_of::<i64>()
+                + size_of::<i64>()
+                + size_of::<u64>()
+                + size_of::<bool>()
+                + size_of::<u16>()
+                + size_of::<Pubkey>()
+                + size_of::<u64>()
+                + size_of::<u64>()
+                + size_of::<[u8; 32]>()
+                + size_of::<Pubkey>()
+                + size_of::<u64>()
+                + size_of::<u8>()
+                + size_of::<i64>()
+                + size_of::<u64>()
+                + size_of::<i64>()
+                + size_of::<u64>()
+                + size_of::<u8>()
+                + size_of::<bool>()
+                + size_of::<u64>()
+                + size_of::<u64>()
+                + size_of::<u64>()
+                + size_of::<u64>()
+                + size_of::<u16>()
+        );
+        assert!(!State::default().paused, "state starts paused");
+        assert!(
+            State::default().pending_owner.is_none(),
+            "state starts with a pending owner"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_address() -> Result<()> {
+        // A single canonical state account, derivable by anyone. `initialize`
+        // creates it with `init`, so a second call at the same address fails.
+        let (address, _bump) = Pubkey::find_program_address(&[State::SEED_PREFIX], &crate::ID);
+        assert_eq!(
+            Pubkey::find_program_address(&[b"state"], &crate::ID).0,
+            address
+        );
+        assert!(!address.is_on_curve(), "state address has a private key");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vault_authority() -> Result<()> {
+        // The vault is owned by a PDA, so the program signs claims itself.
+        let (authority, _bump) = Pubkey::find_program_address(&[SEED_PREFIX_VAULT], &crate::ID);
+        assert!(
+            !authority.is_on_curve(),
+            "vault authority has a private key"
+        );
+        assert_ne!(
+            authority,
+            Pubkey::find_program_address(&[State::SEED_PREFIX], &crate::ID).0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_initializer() -> Result<()> {
+        let admin = Pubkey::new_unique();
+        assert!(is_initializer(Some(admin), &admin));
+        assert!(
+            !is_initializer(Some(Pubkey::new_unique()), &admin),
+            "non-admin initialized"
+        );
+        assert!(
+            !is_initializer(None, &admin),
+            "immutable program initialized"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_emitter() -> Result<()> {
+        let mut emitter = ForeignEmitter::default();
+        let mut address = [0u8; 32];
+        address[12..].copy_from_slice(&[1u8; 20]);
+        save_emitter(&mut emitter, 2, address)?;
+        assert!(emitter.verify(&address), "registered address rejected");
+        emitter.last_sequence = Some(5);
+
+        // Rotating the address forgets the old address and its sequence.
+        let mut new_address = [0u8; 32];
+        new_address[12..].copy_from_slice(&[2u8; 20]);
+        save_emitter(&mut emitter, 2, new_address)?;
+        assert!(emitter.verify(&new_address), "updated address rejected");
+        assert!(!emitter.verify(&address), "rotated address still accepted");
+        assert_eq!(emitter.last_sequence, None);
+
+        // Saving the same address keeps its sequence.
+        emitter.last_sequence = Some(6);
+        save_emitter(&mut emitter, 2, new_address)?;
+        assert_eq!(emitter.last_sequence, Some(6));
+
+        for (chain, address) in [
+            (0, new_address),
+            (wormhole::CHAIN_ID_SOLANA, new_address),
+            (2, [0u8; 32]),
+            // Not left-padded on an EVM chain.
+            (2, [2u8; 32]),
+        ] {
+            assert_eq!(
+                save_emitter(&mut emitter, chain, address).unwrap_err(),
+                HelloWorldError::InvalidForeignEmitter.into()
+            );
+        }
+        assert!(emitter.verify(&new_address), "rejected update was saved");
+
+        // Non-EVM chains register raw 32-byte emitters.
+        let mut emitter = ForeignEmitter::default();
+        save_emitter(&mut emitter, 3, [2u8; 32])?;
+        assert!(emitter.verify(&[2u8; 32]), "raw address rejected");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_merkle_proof() -> Result<()> {
+        let users = [
+            (Pubkey::new_unique(), 10_000u64),
+            (Pubkey::new_unique(), 11_000),
+            (Pubkey::new_unique(), 12_000),
+        ];
+        let leaves: Vec<_> = users
+            .iter()
+            .map(|(user, amount)| merkle_leaf(user, *amount))
+            .collect();
+        let pair = |a: &[u8; 32], b: &[u8; 32]| {
+            if a <= b {
+                keccak::hashv(&[a, b]).0
+            } else {
+                keccak::hashv(&[b, a]).0
+            }
+        };
+        // The odd leaf is promoted as is.
+        let left = pair(&leaves[0], &leaves[1]);
+        let root = pair(&left, &leaves[2]);
+
+        assert!(verify_merkle_proof(
+            &[leaves[1], leaves[2]],
+            &root,
+            &leaves[0]
+        ));
+        assert!(verify_merkle_proof(
+            &[leaves[0], leaves[2]],
+            &root,
+            &leaves[1]
+        ));
+        assert!(verify_merkle_proof(&[left], &root, &leaves[2]));
+
+        let (user, amount) = users[0];
+        assert!(
+            !verify_merkle_proof(
+                &[leaves[1], leaves[2]],
+                &root,
+                &merkle_leaf(&user, amount + 1)
+            ),
+            "wrong amount accepted"
+        );
+        assert!(
+            !verify_merkle_proof(
+                &[leaves[1], leaves[2]],
+                &root,
+                &merkle_leaf(&Pubkey::new_unique(), amount)
+            ),
+            "wrong user accepted"
+        );
+        assert!(
+            !verify_merkle_proof(&[leaves[2]], &root, &leaves[0]),
+            "short proof accepted"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_amount() -> Result<()> {
+        // 8 -> 6 truncates, 8 -> 9 scales up, equal decimals pass through.
+        assert_eq!(normalize_amount(123_456_789, 8, 6)?, 1_234_567);
+        assert_eq!(normalize_amount(123_456_789, 8, 9)?, 1_234_567_890);
+        assert_eq!(normalize_amount(123_456_789, 8, 8)?, 123_456_789);
+        assert_eq!(normalize_amount(u64::MAX, 255, 0)?, 0);
+
+        assert_eq!(
+            normalize_amount(u64::MAX / 10 + 1, 8, 9).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+        assert_eq!(
+            normalize_amount(1, 0, 20).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_credit() -> Result<()> {
+        let mut state = State::default();
+
+        // Credits land in the user state and count towards both totals.
+        let mut alice = UserState::default();
+        assert_eq!(state.credit(&mut alice, 10_000, 6, 6)?, 10_000);
+        assert_eq!(state.credit(&mut alice, 1, 6, 9)?, 1_000);
+        assert_eq!(alice.amount, 11_000);
+
+        // Only whole credited units count, dust stays with the user.
+        let mut bob = UserState::default();
+        assert_eq!(state.credit(&mut bob, 150, 8, 6)?, 1);
+        assert_eq!(bob.amount, 1);
+        assert_eq!(bob.dust, UserState::DUST_PRECISION / 2);
+        assert_eq!(state.total_received, 11_000 + 1);
+        assert_eq!(state.outstanding, 11_000 + 1);
+        assert!(
+            state.has_pending_claims(),
+            "credited allocations not pending"
+        );
+
+        // Totals never wrap around, and a failed credit leaves the user state
+        // untouched.
+        let mut state = State::default();
+        state.credit(&mut UserState::default(), u64::MAX, 6, 6)?;
+        let mut user_state = UserState::default();
+        assert_eq!(
+            state.credit(&mut user_state, 150, 8, 6).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+        assert_eq!(user_state.amount, 0);
+        assert_eq!(user_state.dust, 0);
+        assert_eq!(state.total_received, u64::MAX);
+        assert_eq!(state.outstanding, u64::MAX);
+
+        // Claims made so far don't free room in `total_received`.
+        let mut state = State {
+            total_received: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(
+            state.credit(&mut user_state, 1, 6, 6).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+        assert_eq!(state.outstanding, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pending_owner() -> Result<()> {
+        let new_owner = Pubkey::new_unique();
+        let mut state = State::default();
+        assert!(!state.is_pending_owner(&new_owner));
+
+        state.pending_owner = Some(new_owner);
+        assert!(state.is_pending_owner(&new_owner));
+        assert!(
+            !state.is_pending_owner(&Pubkey::new_unique()),
+            "non-pending key accepted"
+        );
+        assert!(
+            !state.is_pending_owner(&state.owner),
+            "current owner accepted"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_alive() -> Result<()> {
+        // Heartbeats of this program record the slot they arrived at.
+        let mut state = State::default();
+        state.record_alive(&crate::ID, 1_000)?;
+        assert_eq!(state.last_alive_slot, 1_000);
+        state.record_alive(&crate::ID, 1_500)?;
+        assert_eq!(state.last_alive_slot, 1_500);
+
+        // Any other program's heartbeat is rejected.
+        assert_eq!(
+            state
+                .record_alive(&Pubkey::new_unique(), 2_000)
+                .unwrap_err(),
+            HelloWorldError::InvalidMessage.into()
+        );
+        assert_eq!(state.last_alive_slot, 1_500);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_owner_or_operator() -> Result<()> {
+        let operator = Pubkey::new_unique();
+        let mut state = State {
+            owner: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        assert!(state.is_owner_or_operator(&state.owner));
+        assert!(
+            !state.is_owner_or_operator(&Pubkey::default()),
+            "unset operator accepted"
+        );
+
+        state.operator = operator;
+        assert!(state.is_owner_or_operator(&operator));
+        assert!(state.is_owner_or_operator(&state.owner));
+        assert!(
+            !state.is_owner_or_operator(&Pubkey::new_unique()),
+            "non-operator accepted"
+        );
+
+        // Ownership changes stay with the owner.
+        assert!(!state.is_pending_owner(&operator), "operator may accept");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_funded_claimer() -> Result<()> {
+        let mut state = State::default();
+        assert!(state.is_funded_claimer(0), "zero minimum rejected claimer");
+
+        state.min_claimer_sol = 5_000;
+        assert!(state.is_funded_claimer(5_000), "funded claimer rejected");
+        assert!(
+            !state.is_funded_claimer(4_999),
+            "underfunded claimer accepted"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_fee() -> Result<()> {
+        let mut state = State::default();
+        assert_eq!(state.split_fee(10_000, 0), (0, 10_000));
+
+        state.fee_bps = 30;
+        assert_eq!(state.split_fee(10_000, 0), (30, 9_970));
+        // The fee rounds down in the user's favor.
+        assert_eq!(state.split_fee(333, 0), (0, 333));
+        assert_eq!(state.split_fee(334, 0), (1, 333));
+
+        state.fee_bps = 250;
+        assert_eq!(state.split_fee(10_000, 0), (250, 9_750));
+        assert_eq!(state.split_fee(39, 0), (0, 39));
+        assert_eq!(state.split_fee(40, 0), (1, 39));
+
+        // Discounts come off the rate, down to no fee at all.
+        assert_eq!(state.split_fee(10_000, 100), (150, 9_850));
+        assert_eq!(state.split_fee(10_000, 250), (0, 10_000));
+        assert_eq!(state.split_fee(10_000, MAX_FEE_BPS), (0, 10_000));
+
+        state.fee_bps = MAX_FEE_BPS;
+        assert_eq!(state.split_fee(u64::MAX, 0), (u64::MAX, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_referral() -> Result<()> {
+        let mut state = State::default();
+        assert_eq!(state.split_referral(10_000), (0, 10_000));
+
+        // The cut rounds down in the user's favor.
+        state.referral_bps = 500;
+        assert_eq!(state.split_referral(10_000), (500, 9_500));
+        assert_eq!(state.split_referral(19), (0, 19));
+        assert_eq!(state.split_referral(20), (1, 19));
+
+        state.referral_bps = MAX_FEE_BPS;
+        assert_eq!(state.split_referral(u64::MAX), (u64::MAX, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meets_min_claim() -> Result<()> {
+        let mut state = State::default();
+        assert!(state.meets_min_claim(1), "disabled minimum enforced");
+
+        state.min_claim_amount = 1_000;
+        assert!(!state.meets_min_claim(999), "sub-threshold claim accepted");
+        assert!(state.meets_min_claim(1_000));
+        assert!(state.meets_min_claim(1_001));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_needs_review() -> Result<()> {
+        let mut state = State::default();
+        assert!(!state.needs_review(u64::MAX), "disabled review required");
+
+        state.review_threshold = 1_000;
+        assert!(!state.needs_review(1_000));
+        assert!(state.needs_review(1_001), "large allocation not reviewed");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accepts_memo() -> Result<()> {
+        let mut state = State::default();
+        assert!(state.accepts_memo(&NO_MEMO), "optional memo required");
+        assert!(state.accepts_memo(&[1u8; 32]));
+
+        state.require_memo = true;
+        assert!(!state.accepts_memo(&NO_MEMO), "missing memo accepted");
+        assert!(state.accepts_memo(&[1u8; 32]), "memo rejected");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_destination_allowed() -> Result<()> {
+        let mut state = State::default();
+        assert!(state.is_destination_allowed(true));
+        assert!(state.is_destination_allowed(false));
+
+        state.require_registered_destination = true;
+        assert!(
+            state.is_destination_allowed(true),
+            "registered destination rejected"
+        );
+        assert!(
+            !state.is_destination_allowed(false),
+            "unregistered destination accepted"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_should_warn_expiry() -> Result<()> {
+        let mut state = State {
+            claim_deadline: 1_700_000_000,
+            ..Default::default()
+        };
+        assert!(
+            !state.should_warn_expiry(1_699_999_999),
+            "disabled warning fired"
+        );
+
+        state.expiry_warning_secs = 100;
+        assert!(
+            !state.should_warn_expiry(1_699_999_899),
+            "warning fired before its window"
+        );
+        assert!(state.should_warn_expiry(1_699_999_900));
+        assert!(
+            !state.should_warn_expiry(1_700_000_001),
+            "warning fired after the deadline"
+        );
+
+        // The warning fires once per deadline.
+        state.expiry_warned_deadline = state.claim_deadline;
+        assert!(
+            !state.should_warn_expiry(1_699_999_950),
+            "warning fired twice"
+        );
+        state.claim_deadline = 1_800_000_000;
+        assert!(state.should_warn_expiry(1_799_999_950));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_mint_allowed() -> Result<()> {
+        let allowed_mint = Pubkey::new_unique();
+        let state = State {
+            allowed_mint,
+            ..Default::default()
+        };
+        assert!(state.is_mint_allowed(&allowed_mint));
+        assert!(
+            !state.is_mint_allowed(&Pubkey::new_unique()),
+            "other mint allowed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meets_consistency() -> Result<()> {
+        let confirmed = wormhole::Finality::Confirmed as u8;
+        let finalized = wormhole::Finality::Finalized as u8;
+
+        let mut state = State::default();
+        assert!(state.meets_consistency(confirmed));
+        assert!(state.meets_consistency(finalized));
+
+        state.required_consistency = finalized;
+        assert!(
+            !state.meets_consistency(confirmed),
+            "confirmed message accepted"
+        );
+        assert!(state.meets_consistency(finalized));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_expired() -> Result<()> {
+        let mut state = State::default();
+        assert!(!state.is_expired(i64::MAX), "missing deadline expired");
+
+        state.claim_deadline = 1_700_000_000;
+        assert!(
+            !state.is_expired(1_699_999_999),
+            "claim before deadline expired"
+        );
+        assert!(
+            !state.is_expired(1_700_000_000),
+            "claim at deadline expired"
+        );
+        assert!(
+            state.is_expired(1_700_000_001),
+            "claim after deadline accepted"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_window_credit() -> Result<()> {
+        let mut state = State {
+            window_secs: 100,
+            window_limit: 10_000,
+            window_start: 1_000,
+            ..Default::default()
+        };
+
+        // Credits add up to the limit within a window.
+        state.record_window_credit(6_000, 1_000)?;
+        state.record_window_credit(4_000, 1_099)?;
+        assert_eq!(state.window_credited, 10_000);
+
+        // Anything over it is rejected, leaving the window untouched.
+        assert_eq!(
+            state.record_window_credit(1, 1_099).unwrap_err(),
+            CustomError::RateLimited.into()
+        );
+        assert_eq!(
+            state.record_window_credit(u64::MAX, 1_099).unwrap_err(),
+            CustomError::RateLimited.into()
+        );
+        assert_eq!(state.window_credited, 10_000);
+
+        // Once the window rolls, credits are accepted again.
+        state.record_window_credit(1, 1_100)?;
+        assert_eq!(state.window_start, 1_100);
+        assert_eq!(state.window_credited, 1);
+
+        // Not even a new window accepts a single credit over the limit.
+        assert_eq!(
+            state.record_window_credit(10_001, 1_300).unwrap_err(),
+            CustomError::RateLimited.into()
+        );
+
+        // A zero limit disables the rate limit.
+        state.window_limit = 0;
+        state.record_window_credit(u64::MAX, 1_300)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_debit() -> Result<()> {
+        let mut state = State::default();
+        let mut user_state = UserState::default();
+        state.credit(&mut user_state, 10_000, 6, 6)?;
+
+        // Claims draw down both the allocation and the outstanding total.
+        state.debit(&mut user_state, 4_000)?;
+        assert_eq!(user_state.amount, 6_000);
+        assert_eq!(state.outstanding, 6_000);
+
+        // Rejected claims leave both untouched.
+        assert_eq!(
+            state.debit(&mut user_state, 6_001).unwrap_err(),
+            CustomError::InvalidAmount.into()
+        );
+        assert_eq!(state.outstanding, 6_000);
+
+        state.debit(&mut user_state, 6_000)?;
+        assert_eq!(state.outstanding, 0);
+        assert!(!state.has_pending_claims(), "drained allocation pending");
+        assert_eq!(
+            state.debit(&mut user_state, 1).unwrap_err(),
+            CustomError::AlreadyClaimed.into()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_claim() -> Result<()> {
+        let mut state = State::default();
+        let mut user_state = UserState::default();
+        state.credit(&mut user_state, 10_000, 6, 6)?;
+
+        for amount in [1_000, 2_000, 3_000] {
+            state.debit(&mut user_state, amount)?;
+            state.record_claim(amount)?;
+        }
+        assert_eq!(state.total_claimed, 6_000);
+        assert_eq!(state.claim_count, 3);
+
+        // Overflowing totals are rejected without touching either counter.
+        assert_eq!(
+            state.record_claim(u64::MAX).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+        assert_eq!(state.total_claimed, 6_000);
+        assert_eq!(state.claim_count, 3);
+
+        // Claims and credits interleave, with claimed never above received.
+        let mut state = State::default();
+        let mut user_state = UserState::default();
+        for (credit, claim) in [(5_000, 2_000), (1_000, 4_000), (500, 500)] {
+            state.credit(&mut user_state, credit, 6, 6)?;
+            state.debit(&mut user_state, claim)?;
+            state.record_claim(claim)?;
+            assert!(state.total_claimed <= state.total_received);
+        }
+        assert_eq!(state.total_received, 6_500);
+        assert_eq!(state.total_claimed, 6_500);
+        assert_eq!(user_state.amount, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_native_mint() -> Result<()> {
+        assert!(is_native_mint(&spl_token::native_mint::ID));
+        assert!(
+            !is_native_mint(&Pubkey::new_unique()),
+            "SPL mint treated as native"
+        );
+        // Token-2022 has its own wrapped SOL mint, which this program doesn't
+        // unwrap.
+        assert!(!is_native_mint(&spl_token_2022::native_mint::ID));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_close_state() -> Result<()> {
+        // An empty deployment can be closed.
+        let mut state = State::default();
+        assert!(
+            !state.has_pending_claims(),
+            "empty state has pending claims"
+        );
+        // Fully claimed allocations don't hold the state open.
+        let mut user_state = UserState::default();
+        state.credit(&mut user_state, 1, 6, 6)?;
+        assert!(state.has_pending_claims(), "allocation not pending");
+        state.debit(&mut user_state, 1)?;
+        assert!(!state.has_pending_claims(), "claimed allocation pending");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_2022() -> Result<()> {
+        // Claims accept both token programs.
+        assert!(TokenInterface::ids().contains(&spl_token::ID));
+        assert!(TokenInterface::ids().contains(&spl_token_2022::ID));
+
+        // Token-2022 vaults load like legacy ones.
+        let mint = Pubkey::new_unique();
+        let (vault_authority, _bump) =
+            Pubkey::find_program_address(&[SEED_PREFIX_VAULT], &crate::ID);
+        let mut data = vec![0; spl_token_2022::state::Account::LEN];
+        spl_token_2022::state::Account {
+            mint,
+            owner: vault_authority,
+            amount: 10_000,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let key = get_associated_token_address_with_program_id(
+            &vault_authority,
+            &mint,
+            &spl_token_2022::ID,
+        );
+        let mut lamports = 0;
+        let info = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &spl_token_2022::ID,
+            false,
+            0,
+        );
+        let vault = InterfaceAccount::<TokenAccount>::try_from(&info)?;
+        assert_eq!(vault.mint, mint);
+        assert_eq!(vault.owner, vault_authority);
+        assert_eq!(vault.amount, 10_000);
+
+        // Each token program has its own vault address.
+        assert_ne!(
+            key,
+            get_associated_token_address_with_program_id(&vault_authority, &mint, &spl_token::ID)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_result() -> Result<()> {
+        let result = ClaimResult {
+            amount_transferred: 9_990,
+            remaining: 1_000,
+        };
+
+        // Anchor sets the Borsh encoding as the return data.
+        let data = result.try_to_vec()?;
+        assert_eq!(data.len(), 16);
+        assert_eq!(ClaimResult::try_from_slice(&data)?, result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_view() -> Result<()> {
+        let user = Pubkey::new_unique();
+        let funded = UserState {
+            user,
+            amount: 10_000,
+            ..Default::default()
+        };
+        let claimed = UserState {
+            user,
+            claimed: 10_000,
+            ..Default::default()
+        };
+
+        let view = ClaimView::new(user, Some(&funded));
+        assert_eq!(
+            (view.user, view.amount, view.claimed),
+            (user, 10_000, false)
+        );
+        let view = ClaimView::new(user, Some(&claimed));
+        assert_eq!((view.amount, view.claimed), (0, true));
+        let view = ClaimView::new(user, None);
+        assert_eq!((view.amount, view.claimed), (0, false));
+
+        // Anchor sets the Borsh encoding as the return data.
+        let data = view.try_to_vec()?;
+        assert_eq!(data.len(), 32 + 8 + 1);
+        assert_eq!(ClaimView::try_from_slice(&data)?, view);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_user_state() -> Result<()> {
+        let user = Pubkey::new_unique();
+        let (key, _bump) = UserState::address(&user);
+        let owner = crate::ID;
+        let mut lamports = 0;
+
+        // A user state that was never created reads as none.
+        let mut data = Vec::new();
+        let info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert!(read_user_state(&info)?.is_none());
+
+        let user_state = UserState {
+            user,
+            amount: 10_000,
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        user_state.try_serialize(&mut data)?;
+        let info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            read_user_state(&info)?.map(|read| read.amount),
+            Some(10_000)
+        );
+
+        // Accounts of other programs are rejected.
+        let other = Pubkey::new_unique();
+        let info = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &other,
+            false,
+            0,
+        );
+        assert!(read_user_state(&info).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_user_amounts() -> Result<()> {
+        let user_states: Vec<_> = [10_000, 11_000, 12_000]
+            .into_iter()
+            .map(|amount| UserState {
+                user: Pubkey::new_unique(),
+                amount,
+                ..Default::default()
+            })
+            .collect();
+
+        let packed = pack_user_amounts(&user_states);
+        assert_eq!(packed.len(), 3 * USER_AMOUNT_LEN);
+
+        for (i, user_state) in user_states.iter().enumerate() {
+            let entry = &packed[i * USER_AMOUNT_LEN..(i + 1) * USER_AMOUNT_LEN];
+            assert_eq!(&entry[..32], user_state.user.as_ref());
+            assert_eq!(
+                u64::from_le_bytes(entry[32..].try_into().unwrap()),
+                user_state.amount
+            );
+        }
+
+        Ok(())
+    }
+
+    thread_local! {
+        /// Unix timestamp of the [`Clock`] read by the current test's
+        /// instructions, set with [`set_clock`].
+        static UNIX_TIMESTAMP: Cell<i64> = const { Cell::new(0) };
+        /// Data logged by the current test's instructions, such as events.
+        static LOGGED_DATA: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Has the current test's instructions run at `unix_timestamp`.
+    fn set_clock(unix_timestamp: i64) {
+        UNIX_TIMESTAMP.with(|now| now.set(unix_timestamp));
+    }
+
+    /// Events of type `T` emitted by the current test's instructions, oldest
+    /// first.
+    fn logged_events<T: Discriminator + AnchorDeserialize>() -> Vec<T> {
+        LOGGED_DATA.with(|logged| {
+            logged
+                .borrow()
+                .iter()
+                .filter(|data| data.starts_with(&T::DISCRIMINATOR))
+                .map(|data| T::deserialize(&mut &data[8..]).unwrap())
+                .collect()
+        })
+    }
+
+    /// Syscalls the program makes outside the runtime, installed by
+    /// [`install_stubs`].
+    struct TestStubs;
+
+    impl SyscallStubs for TestStubs {
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        fn sol_log_data(&self, data: &[&[u8]]) {
+            LOGGED_DATA.with(|logged| logged.borrow_mut().push(data.concat()));
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: UNIX_TIMESTAMP.with(Cell::get),
+                ..Default::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+
+        /// Runs the invoked token, system or Wormhole program in place, with
+        /// this program's PDAs signing by their seeds.
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            let signers = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &crate::ID))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let accounts: Vec<_> = instruction
+                .accounts
+                .iter()
+                .map(|meta| {
+                    let mut info = account_infos
+                        .iter()
+                        .find(|info| *info.key == meta.pubkey)
+                        .expect("invoked account not passed")
+                        .clone();
+                    info.is_signer |= signers.contains(info.key);
+                    info
+                })
+                .collect();
+
+            match instruction.program_id {
+                spl_token::ID => spl_token::processor::Processor::process(
+                    &spl_token::ID,
+                    &accounts,
+                    &instruction.data,
+                ),
+                // Without a network feature the Wormhole program shares the
+                // system program's ID, so its instructions are told apart by
+                // their data.
+                system_program::ID => {
+                    match wormhole::Instruction::try_from_slice(&instruction.data) {
+                        Ok(wormhole::Instruction::PostMessage {
+                            batch_id,
+                            payload,
+                            finality,
+                        }) => post_message(&accounts, batch_id, payload, finality),
+                        _ => system(&accounts, &instruction.data),
+                    }
+                }
+                program_id => panic!("invoked unexpected program {program_id}"),
+            }
+        }
+    }
+
+    /// Runs the system program's `CreateAccount`, `Transfer`, `Allocate` or
+    /// `Assign`, the only system instructions invoked by the instructions
+    /// tested. Accounts already allocated are refused like the runtime does.
+    fn system(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        let instruction = limited_deserialize(data, data.len() as u64)
+            .map_err(|_| ProgramError::InvalidInstructionData)?;
+        let account = &accounts[0];
+        if !account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let in_use = !account.data_is_empty() || *account.owner != system_program::ID;
+        match instruction {
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                let to = &accounts[1];
+                if !to.is_signer {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                if to.lamports() > 0 {
+                    return Err(ProgramError::Custom(
+                        SystemError::AccountAlreadyInUse as u32,
+                    ));
+                }
+                create_account(account, to, lamports, space as usize, &owner)
+            }
+            SystemInstruction::Transfer { lamports } => {
+                **account.try_borrow_mut_lamports()? -= lamports;
+                **accounts[1].try_borrow_mut_lamports()? += lamports;
+                Ok(())
+            }
+            SystemInstruction::Allocate { .. } | SystemInstruction::Assign { .. } if in_use => Err(
+                ProgramError::Custom(SystemError::AccountAlreadyInUse as u32),
+            ),
+            SystemInstruction::Allocate { space } => account.realloc(space as usize, true),
+            SystemInstruction::Assign { owner } => {
+                account.assign(&owner);
+                Ok(())
+            }
+            _ => panic!("invoked unexpected system instruction"),
+        }
+    }
+
+    /// Creates `to` with `space` bytes owned by `owner`, funded with
+    /// `lamports` from `from`.
+    fn create_account(
+        from: &AccountInfo,
+        to: &AccountInfo,
+        lamports: u64,
+        space: usize,
+        owner: &Pubkey,
+    ) -> ProgramResult {
+        **from.try_borrow_mut_lamports()? -= lamports;
+        **to.try_borrow_mut_lamports()? += lamports;
+        to.realloc(space, true)?;
+        to.assign(owner);
+        Ok(())
+    }
+
+    /// Runs the Wormhole program's `PostMessage`: checks that the fee was
+    /// paid, writes the message into its account with the emitter's current
+    /// sequence and increments it, creating the sequence account on the
+    /// first message.
+    fn post_message(
+        accounts: &[AccountInfo],
+        batch_id: u32,
+        payload: Vec<u8>,
+        finality: wormhole::Finality,
+    ) -> ProgramResult {
+        let [bridge, message, emitter, sequence, payer, fee_collector, ..] = accounts else {
+            panic!("invoked post_message without its accounts");
+        };
+        if !message.is_signer || !emitter.is_signer || !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut bridge_data = wormhole::BridgeData::try_from_slice(&bridge.data.borrow())?;
+        if fee_collector.lamports() < bridge_data.last_lamports + bridge_data.fee() {
+            return Err(ProgramError::InsufficientFunds);
+        }
+        bridge_data.last_lamports = fee_collector.lamports();
+        bridge_data.serialize(&mut &mut bridge.data.borrow_mut()[..])?;
+
+        if sequence.data_is_empty() {
+            let space = size_of::<u64>();
+            let lamports = Rent::default().minimum_balance(space);
+            create_account(
+                payer,
+                sequence,
+                lamports,
+                space,
+                &wormhole::program::Wormhole::id(),
+            )?;
+        }
+        let mut tracker = wormhole::SequenceTracker::try_from_slice(&sequence.data.borrow())?;
+
+        let mut data = b"msg".to_vec();
+        wormhole::PostedVaaMeta {
+            version: 1,
+            finality: finality as u8,
+            batch_id,
+            sequence: tracker.sequence,
+            emitter_chain: wormhole::CHAIN_ID_SOLANA,
+            emitter_address: emitter.key.to_bytes(),
+            ..Default::default()
+        }
+        .serialize(&mut data)?;
+        (payload.len() as u32).serialize(&mut data)?;
+        data.extend_from_slice(&payload);
+        let lamports = Rent::default().minimum_balance(data.len());
+        create_account(
+            payer,
+            message,
+            lamports,
+            data.len(),
+            &wormhole::program::Wormhole::id(),
+        )?;
+        message.data.borrow_mut().copy_from_slice(&data);
+
+        tracker.sequence += 1;
+        tracker.serialize(&mut &mut sequence.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// Installs [`TestStubs`], once for all tests.
+    fn install_stubs() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestStubs));
+        });
+    }
+
+    /// Account handed to instruction account validation, laid out like the
+    /// runtime serializes it so that it can be resized. Its fields are leaked,
+    /// so it lives as long as the accounts borrowing it.
+    fn account_info(
+        key: Pubkey,
+        is_signer: bool,
+        lamports: u64,
+        data: Vec<u8>,
+        owner: Pubkey,
+    ) -> AccountInfo<'static> {
+        // The original data length is read right before the key.
+        let key_words: &'static mut [u32; 9] = Box::leak(Box::new([0; 9]));
+        key_words[0] = data.len() as u32;
+        // The current data length sits right before the data, which is
+        // followed by the room it may grow into.
+        let data_words = Box::leak(
+            vec![0u64; 2 + (data.len() + MAX_PERMITTED_DATA_INCREASE) / 8].into_boxed_slice(),
+        );
+        data_words[0] = data.len() as u64;
+
+        let (key, data) = unsafe {
+            let key_ptr = key_words.as_mut_ptr().add(1) as *mut Pubkey;
+            key_ptr.write(key);
+            let data_ptr = data_words.as_mut_ptr().add(1) as *mut u8;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), data_ptr, data.len());
+            (
+                &*key_ptr,
+                std::slice::from_raw_parts_mut(data_ptr, data.len()),
+            )
+        };
+        AccountInfo::new(
+            key,
+            is_signer,
+            true,
+            Box::leak(Box::new(lamports)),
+            data,
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )
+    }
+
+    /// Program account `id`. Also stands in for absent optional accounts when
+    /// `id` is this program.
+    fn program_info(id: Pubkey) -> AccountInfo<'static> {
+        let mut info = account_info(id, false, 1, Vec::new(), Pubkey::default());
+        info.executable = true;
+        info.is_writable = false;
+        info
+    }
+
+    /// Wallet `key` holding one SOL.
+    fn wallet_info(key: Pubkey, is_signer: bool) -> AccountInfo<'static> {
+        account_info(key, is_signer, 1_000_000_000, Vec::new(), Pubkey::default())
+    }
+
+    /// Rent exempt Anchor account `data` at `key`, owned by this program.
+    fn anchor_info<T: AccountSerialize>(key: Pubkey, data: &T) -> AccountInfo<'static> {
+        let mut serialized = Vec::new();
+        data.try_serialize(&mut serialized).unwrap();
+        let lamports = Rent::default().minimum_balance(serialized.len());
+        account_info(key, false, lamports, serialized, crate::ID)
+    }
+
+    /// Like [`anchor_info`], padded to `len` bytes so that the data can grow
+    /// up to them when written back.
+    fn sized_anchor_info<T: AccountSerialize>(
+        key: Pubkey,
+        data: &T,
+        len: usize,
+    ) -> AccountInfo<'static> {
+        let mut serialized = Vec::new();
+        data.try_serialize(&mut serialized).unwrap();
+        serialized.resize(len, 0);
+        let lamports = Rent::default().minimum_balance(serialized.len());
+        account_info(key, false, lamports, serialized, crate::ID)
+    }
+
+    /// State account holding `state`, sized for any change to be written back.
+    fn state_info(state: &State) -> AccountInfo<'static> {
+        let address = Pubkey::find_program_address(&[State::SEED_PREFIX], &crate::ID).0;
+        sized_anchor_info(address, state, State::MAXIMUM_SIZE)
+    }
+
+    /// Legacy token account `key` of `owner` holding `amount` of `mint`.
+    /// Wrapped SOL accounts back their amount with lamports on top of rent.
+    fn token_account_info(
+        key: Pubkey,
+        mint: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+    ) -> AccountInfo<'static> {
+        let rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+        let (is_native, lamports) = if is_native_mint(&mint) {
+            (COption::Some(rent), rent + amount)
+        } else {
+            (COption::None, rent)
+        };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            is_native,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(key, false, lamports, data, spl_token::ID)
+    }
+
+    /// Legacy mint `key` with 6 decimals.
+    fn mint_info(key: Pubkey) -> AccountInfo<'static> {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(key, false, 1_000_000, data, spl_token::ID)
+    }
+
+    /// Sysvar account holding `sysvar`.
+    fn sysvar_info<T: SolanaSysvar>(sysvar: &T) -> AccountInfo<'static> {
+        let mut info = account_info(T::id(), false, 1, vec![0; T::size_of()], sysvar::ID);
+        sysvar.to_account_info(&mut info).unwrap();
+        info
+    }
+
+    /// Accounts of a claim from [`ClaimToken`], in order, of a `wallet` owning
+    /// the destination token account with an allocation of 10_000.
+    struct ClaimFixture {
+        wallet: Pubkey,
+        mint: Pubkey,
+        user: Pubkey,
+        state: State,
+        accounts: Vec<AccountInfo<'static>>,
+    }
+
+    impl ClaimFixture {
+        /// Index of the `user_state` account.
+        const USER_STATE: usize = 2;
+        /// Index of the `claimer` account.
+        const CLAIMER: usize = 3;
+        /// Index of the `delegate` account.
+        const DELEGATE: usize = 4;
+        /// Index of the `vault` account.
+        const VAULT: usize = 5;
+        /// Index of the `vault_authority` account.
+        const VAULT_AUTHORITY: usize = 6;
+        /// Index of the `mint` account.
+        const MINT: usize = 7;
+        /// Index of the `deny_entry` account.
+        const DENY_ENTRY: usize = 10;
+        /// Index of the `fee_account` account.
+        const FEE_ACCOUNT: usize = 11;
+        /// Index of the `recipient` account.
+        const RECIPIENT: usize = 12;
+        /// Index of the `referrer` account.
+        const REFERRER: usize = 13;
+        /// Index of the `token_program` account.
+        const TOKEN_PROGRAM: usize = 14;
+
+        fn new() -> Self {
+            Self::with_mint(Pubkey::new_unique())
+        }
+
+        /// Like [`ClaimFixture::new`], for an allocation of `mint`.
+        fn with_mint(mint: Pubkey) -> Self {
+            let wallet = Pubkey::new_unique();
+            let user = Pubkey::new_unique();
+            let (vault_authority, vault_bump) =
+                Pubkey::find_program_address(&[SEED_PREFIX_VAULT], &crate::ID);
+            let state = State {
+                allowed_mint: mint,
+                outstanding: 10_000,
+                vault_bump,
+                ..Default::default()
+            };
+            let vault = get_associated_token_address_with_program_id(
+                &vault_authority,
+                &mint,
+                &spl_token::ID,
+            );
+            let (deny_entry, _bump) = Pubkey::find_program_address(
+                &[DenyEntry::SEED_PREFIX, wallet.as_ref()],
+                &crate::ID,
+            );
+            let user_state = UserState {
+                user,
+                amount: 10_000,
+                mint,
+                ..Default::default()
+            };
+
+            let accounts = vec![
+                anchor_info(
+                    Pubkey::find_program_address(&[State::SEED_PREFIX], &crate::ID).0,
+                    &state,
+                ),
+                token_account_info(user, mint, wallet, 0),
+                anchor_info(UserState::address(&user).0, &user_state),
+                wallet_info(wallet, true),
+                program_info(crate::ID),
+                token_account_info(vault, mint, vault_authority, 10_000),
+                account_info(vault_authority, false, 0, Vec::new(), Pubkey::default()),
+                mint_info(mint),
+                program_info(crate::ID),
+                program_info(crate::ID),
+                account_info(deny_entry, false, 0, Vec::new(), Pubkey::default()),
+                program_info(crate::ID),
+                program_info(crate::ID),
+                program_info(crate::ID),
+                program_info(spl_token::ID),
+            ];
+            Self {
+                wallet,
+                mint,
+                user,
+                state,
+                accounts,
+            }
+        }
+
+        /// Names `delegate` as the allocation's delegate.
+        fn set_delegate(&mut self, delegate: Pubkey) {
+            let user_state = UserState {
+                user: self.user,
+                amount: 10_000,
+                delegate: Some(delegate),
+                mint: self.mint,
+                ..Default::default()
+            };
+            self.accounts[Self::USER_STATE] =
+                anchor_info(UserState::address(&self.user).0, &user_state);
+        }
+
+        /// Has `delegate` sign the claim instead of the owning wallet.
+        fn sign_as(&mut self, delegate: Pubkey) {
+            self.accounts[Self::CLAIMER].is_signer = false;
+            self.accounts[Self::DELEGATE] = wallet_info(delegate, true);
+        }
+
+        /// Accounts of an idempotent claim with `idempotency_key`, presenting
+        /// the receipt `receipt_signer` got for it.
+        fn idempotent_accounts(
+            &self,
+            receipt_signer: &Pubkey,
+            idempotency_key: &[u8; 32],
+        ) -> Vec<AccountInfo<'static>> {
+            let (receipt, _bump) = Pubkey::find_program_address(
+                &[
+                    ClaimReceipt::SEED_PREFIX,
+                    self.user.as_ref(),
+                    receipt_signer.as_ref(),
+                    idempotency_key,
+                ],
+                &crate::ID,
+            );
+            let mut accounts = self.accounts.clone();
+            accounts.extend([
+                anchor_info(
+                    receipt,
+                    &ClaimReceipt {
+                        claimed: true,
+                        amount: 4_000,
+                    },
+                ),
+                wallet_info(self.wallet, true),
+                program_info(system_program::ID),
+            ]);
+            accounts
+        }
+
+        /// Accounts of a [`BatchClaim`] paying the fixture's user, with
+        /// `deny_entry` in place of its deny entry.
+        fn batch_accounts(&self, deny_entry: AccountInfo<'static>) -> Vec<AccountInfo<'static>> {
+            let accounts = &self.accounts;
+            vec![
+                accounts[0].clone(),
+                accounts[Self::VAULT].clone(),
+                accounts[Self::VAULT_AUTHORITY].clone(),
+                accounts[Self::MINT].clone(),
+                program_info(crate::ID),
+                accounts[Self::TOKEN_PROGRAM].clone(),
+                accounts[1].clone(),
+                accounts[Self::CLAIMER].clone(),
+                accounts[Self::USER_STATE].clone(),
+                deny_entry,
+            ]
+        }
+
+        /// Validates the accounts as a [`ClaimToken`].
+        fn try_accounts(&self) -> Result<ClaimToken<'static>> {
+            ClaimToken::try_accounts(
+                &crate::ID,
+                &mut &self.accounts[..],
+                &[],
+                &mut BTreeMap::new(),
+                &mut BTreeSet::new(),
+            )
+        }
+    }
+
+    /// Accounts of a [`ReceiveMessage`], in order, relaying a message from the
+    /// emitter registered for chain 2, followed by the [`UserState`] accounts
+    /// of its entries. Nothing is staked and no user state exists yet.
+    struct ReceiveFixture {
+        payer: Pubkey,
+        vaa_hash: [u8; 32],
+        accounts: Vec<AccountInfo<'static>>,
+    }
+
+    impl ReceiveFixture {
+        /// Chain of the registered emitter.
+        const CHAIN: u16 = 2;
+        /// Sequence of the relayed message.
+        const SEQUENCE: u64 = 7;
+        /// Index of the `foreign_emitter` account.
+        const FOREIGN_EMITTER: usize = 3;
+        /// Index of the `state` account.
+        const STATE: usize = 4;
+        /// Index of the `received` account.
+        const RECEIVED: usize = 5;
+        /// Index of the `relayer_stake` account.
+        const RELAYER_STAKE: usize = 6;
+        /// Index of the first user state account.
+        const USER_STATES: usize = 9;
+
+        /// Relays `message` to a deployment with `state`, paying claims in a
+        /// mint with 6 decimals.
+        fn new(message: &HelloWorldMessage, state: &State) -> Self {
+            Self::with_sequence(message, state, Self::SEQUENCE)
+        }
+
+        /// Like [`new`](Self::new), with the message posted at `sequence`.
+        fn with_sequence(message: &HelloWorldMessage, state: &State, sequence: u64) -> Self {
+            let payer = Pubkey::new_unique();
+            let mut vaa_hash = [9; 32];
+            vaa_hash[..8].copy_from_slice(&sequence.to_le_bytes());
+            let mut emitter_address = [1; 32];
+            emitter_address[..12].fill(0);
+
+            let payload = message.try_to_vec().unwrap();
+            let mut posted = b"vaa".to_vec();
+            wormhole::PostedVaaMeta {
+                sequence,
+                emitter_chain: Self::CHAIN,
+                emitter_address,
+                ..Default::default()
+            }
+            .serialize(&mut posted)
+            .unwrap();
+            (payload.len() as u32).serialize(&mut posted).unwrap();
+            posted.extend_from_slice(&payload);
+            let wormhole_program = wormhole::program::Wormhole::id();
+            let posted_address = Pubkey::find_program_address(
+                &[wormhole::SEED_PREFIX_POSTED_VAA, &vaa_hash],
+                &wormhole_program,
+            )
+            .0;
+
+            let address = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &crate::ID).0;
+            let foreign_emitter = ForeignEmitter {
+                chain: Self::CHAIN,
+                address: emitter_address,
+                last_sequence: None,
+            };
+            let received = address(&[
+                Received::SEED_PREFIX,
+                &Self::CHAIN.to_le_bytes(),
+                &sequence.to_le_bytes(),
+            ]);
+
+            let mut accounts = vec![
+                wallet_info(payer, true),
+                program_info(wormhole_program),
+                account_info(posted_address, false, 1_000_000, posted, wormhole_program),
+                sized_anchor_info(
+                    address(&[ForeignEmitter::SEED_PREFIX, &Self::CHAIN.to_le_bytes()]),
+                    &foreign_emitter,
+                    ForeignEmitter::MAXIMUM_SIZE,
+                ),
+                state_info(state),
+                account_info(received, false, 0, Vec::new(), system_program::ID),
+                program_info(crate::ID),
+                mint_info(state.allowed_mint),
+                program_info(system_program::ID),
+            ];
+            if let HelloWorldMessage::MultiUserInfo { entries, .. } = message {
+                // A user repeated in the message shares its account.
+                let mut user_states = BTreeMap::new();
+                accounts.extend(entries.iter().map(|(user, _amount)| {
+                    user_states
+                        .entry(*user)
+                        .or_insert_with(|| {
+                            let address = UserState::address(user).0;
+                            account_info(address, false, 0, Vec::new(), system_program::ID)
+                        })
+                        .clone()
+                }));
+            }
+            Self {
+                payer,
+                vaa_hash,
+                accounts,
+            }
+        }
+
+        /// Locks `amount` lamports in the payer's relayer stake.
+        fn stake(&mut self, amount: u64) {
+            let stake = RelayerStake {
+                relayer: self.payer,
+                amount,
+            };
+            let address = Pubkey::find_program_address(
+                &[RelayerStake::SEED_PREFIX, self.payer.as_ref()],
+                &crate::ID,
+            )
+            .0;
+            self.accounts[Self::RELAYER_STAKE] = anchor_info(address, &stake);
+        }
+
+        /// Runs `receive_message` on the accounts.
+        fn receive(&self) -> ProgramResult {
+            let data = instruction::ReceiveMessage {
+                vaa_hash: self.vaa_hash,
+            }
+            .data();
+            entry(&crate::ID, &self.accounts, &data)
+        }
+
+        /// User state of the `i`th entry.
+        fn user_state(&self, i: usize) -> UserState {
+            Account::<UserState>::try_from(&self.accounts[Self::USER_STATES + i])
+                .unwrap()
+                .into_inner()
+        }
+    }
+
+    #[test]
+    fn test_claim_signer() -> Result<()> {
+        // The wallet owning the destination signs its own claim.
+        let fixture = ClaimFixture::new();
+        let claim = fixture.try_accounts()?;
+        assert_eq!(claim.claimer.key(), fixture.wallet);
+        assert_eq!(claim.state.allowed_mint, fixture.state.allowed_mint);
+        assert_eq!(claim.user.key(), fixture.user);
+        assert_eq!(claim.user.mint, fixture.mint);
+        assert!(claim.user_state.is_some(), "user state not loaded");
+        assert!(claim.fee_account.is_none(), "absent fee account loaded");
+
+        // Naming the owning wallet without its signature is refused.
+        let mut fixture = ClaimFixture::new();
+        fixture.accounts[ClaimFixture::CLAIMER].is_signer = false;
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(ErrorCode::AccountNotSigner.into())
+        );
+
+        // So is any other signer.
+        let mut fixture = ClaimFixture::new();
+        fixture.accounts[ClaimFixture::CLAIMER] = wallet_info(Pubkey::new_unique(), true);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(CustomError::InvalidUser.into())
+        );
+
+        Ok(())
+    }
+
+    /// Validates `accounts` as a [`ClaimTokenIdempotent`] with
+    /// `idempotency_key`.
+    fn try_idempotent(
+        accounts: &[AccountInfo<'static>],
+        idempotency_key: &[u8; 32],
+    ) -> Result<ClaimTokenIdempotent<'static>> {
+        ClaimTokenIdempotent::try_accounts(
+            &crate::ID,
+            &mut &accounts[..],
+            idempotency_key,
+            &mut BTreeMap::new(),
+            &mut BTreeSet::new(),
+        )
+    }
+
+    #[test]
+    fn test_claim_user_state_seeds() {
+        // The user state of another token account can't be claimed through
+        // this one, even for an allocation naming it.
+        let mut fixture = ClaimFixture::new();
+        let other = Pubkey::new_unique();
+        let user_state = UserState {
+            user: fixture.user,
+            amount: 10_000,
+            mint: fixture.mint,
+            ..Default::default()
+        };
+        fixture.accounts[ClaimFixture::USER_STATE] =
+            anchor_info(UserState::address(&other).0, &user_state);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(ErrorCode::ConstraintSeeds.into())
+        );
+
+        // Nor can an account at any other address.
+        fixture.accounts[ClaimFixture::USER_STATE] = anchor_info(Pubkey::new_unique(), &user_state);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(ErrorCode::ConstraintSeeds.into())
+        );
+    }
+
+    #[test]
+    fn test_claim_receipt_signer() -> Result<()> {
+        install_stubs();
+        let fixture = ClaimFixture::new();
+        let idempotency_key = [7; 32];
+
+        // A retry by the signer of the first claim finds its receipt.
+        let accounts = fixture.idempotent_accounts(&fixture.wallet, &idempotency_key);
+        let receipt = try_idempotent(&accounts, &idempotency_key)?.receipt;
+        assert!(receipt.claimed, "receipt not found");
+        assert_eq!(receipt.amount, 4_000);
+
+        // A different signer reusing the key, such as a previous owner of the
+        // token account, can't present that receipt.
+        let previous_owner = Pubkey::new_unique();
+        let accounts = fixture.idempotent_accounts(&previous_owner, &idempotency_key);
+        assert_eq!(
+            try_idempotent(&accounts, &idempotency_key).err(),
+            Some(ErrorCode::ConstraintSeeds.into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_with_memo_owner_only() -> Result<()> {
+        let data = instruction::ClaimWithMemo {
+            amount: 1_000,
+            memo: [1; 32],
+        }
+        .data();
+
+        // A signer other than the wallet owning the destination is refused
+        // before anything is transferred.
+        let mut fixture = ClaimFixture::new();
+        fixture.accounts[ClaimFixture::CLAIMER] = wallet_info(Pubkey::new_unique(), true);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &data),
+            Err(Error::from(CustomError::InvalidUser).into())
+        );
+        assert_eq!(
+            InterfaceAccount::<TokenAccount>::try_from(&fixture.accounts[ClaimFixture::VAULT])?
+                .amount,
+            10_000
+        );
+
+        // Neither can the owner be named without signing.
+        let mut fixture = ClaimFixture::new();
+        fixture.accounts[ClaimFixture::CLAIMER].is_signer = false;
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &data),
+            Err(Error::from(ErrorCode::AccountNotSigner).into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_delegate() -> Result<()> {
+        install_stubs();
+        let delegate = Pubkey::new_unique();
+
+        // The delegate named by the message signs on the owner's behalf, and
+        // the tokens still go to the owner's token account.
+        let mut fixture = ClaimFixture::new();
+        fixture.set_delegate(delegate);
+        fixture.sign_as(delegate);
+        let claim = fixture.try_accounts()?;
+        assert_eq!(claim_signer(&claim), delegate);
+        assert_eq!(claim.user.key(), fixture.user);
+        assert_eq!(claim.user.owner, fixture.wallet);
+
+        // The owner may still claim itself.
+        let mut fixture = ClaimFixture::new();
+        fixture.set_delegate(delegate);
+        assert_eq!(claim_signer(&fixture.try_accounts()?), fixture.wallet);
+
+        // Any other delegate is refused, whether the allocation names a
+        // different one or none at all.
+        let mut fixture = ClaimFixture::new();
+        fixture.set_delegate(delegate);
+        fixture.sign_as(Pubkey::new_unique());
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(CustomError::UnauthorizedDelegate.into())
+        );
+        let mut fixture = ClaimFixture::new();
+        fixture.sign_as(delegate);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(CustomError::UnauthorizedDelegate.into())
+        );
+
+        // Delegates get their own idempotency receipts.
+        let mut fixture = ClaimFixture::new();
+        fixture.set_delegate(delegate);
+        fixture.sign_as(delegate);
+        let idempotency_key = [7; 32];
+        let accounts = fixture.idempotent_accounts(&delegate, &idempotency_key);
+        assert!(try_idempotent(&accounts, &idempotency_key)?.receipt.claimed);
+        let accounts = fixture.idempotent_accounts(&fixture.wallet, &idempotency_key);
+        assert_eq!(
+            try_idempotent(&accounts, &idempotency_key).err(),
+            Some(ErrorCode::ConstraintSeeds.into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operator_roles() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let operator = Pubkey::new_unique();
+        let state = State {
+            owner,
+            operator,
+            ..Default::default()
+        };
+        let chain = 2;
+        let mut address = [1; 32];
+        address[..12].fill(0);
+        let foreign_emitter = ForeignEmitter {
+            chain,
+            address,
+            last_sequence: Some(5),
+        };
+        let foreign_emitter_address = Pubkey::find_program_address(
+            &[ForeignEmitter::SEED_PREFIX, &chain.to_le_bytes()[..]],
+            &crate::ID,
+        )
+        .0;
+        let emitter_accounts = |authority: Pubkey, system_program: bool| {
+            let mut accounts = vec![
+                wallet_info(authority, true),
+                state_info(&state),
+                anchor_info(foreign_emitter_address, &foreign_emitter),
+            ];
+            if system_program {
+                accounts.push(program_info(system_program::ID));
+            }
+            accounts
+        };
+
+        // The operator manages emitters just like the owner, anyone else is
+        // refused.
+        let register = instruction::RegisterEmitter { chain, address }.data();
+        let update = instruction::UpdateEmitter {
+            chain,
+            new_address: address,
+        }
+        .data();
+        let deregister = instruction::DeregisterEmitter { chain }.data();
+        for authority in [owner, operator] {
+            assert_eq!(
+                entry(&crate::ID, &emitter_accounts(authority, true), &register),
+                Ok(())
+            );
+            assert_eq!(
+                entry(&crate::ID, &emitter_accounts(authority, false), &update),
+                Ok(())
+            );
+            assert_eq!(
+                entry(&crate::ID, &emitter_accounts(authority, false), &deregister),
+                Ok(())
+            );
+        }
+        let stranger = Pubkey::new_unique();
+        for (data, system_program) in [(&register, true), (&update, false), (&deregister, false)] {
+            assert_eq!(
+                entry(
+                    &crate::ID,
+                    &emitter_accounts(stranger, system_program),
+                    data
+                ),
+                Err(Error::from(CustomError::InvalidOwner).into())
+            );
+        }
+
+        // Fees and ownership stay with the owner.
+        let set_fee = instruction::SetFee {
+            fee_bps: 100,
+            fee_recipient: operator,
+        }
+        .data();
+        let propose_owner = instruction::ProposeOwner {
+            new_owner: operator,
+        }
+        .data();
+        for data in [&set_fee, &propose_owner] {
+            let accounts = [state_info(&state), wallet_info(operator, true)];
+            assert_eq!(
+                entry(&crate::ID, &accounts, data),
+                Err(Error::from(CustomError::InvalidOwner).into())
+            );
+            let accounts = [state_info(&state), wallet_info(owner, true)];
+            assert_eq!(entry(&crate::ID, &accounts, data), Ok(()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_claim_deny_entries() -> Result<()> {
+        install_stubs();
+        let data = instruction::BatchClaim {}.data();
+
+        // A denied user fails the batch it is part of.
+        let fixture = ClaimFixture::new();
+        let deny_entry = anchor_info(
+            DenyEntry::address(&fixture.wallet).0,
+            &DenyEntry {
+                wallet: fixture.wallet,
+            },
+        );
+        assert_eq!(
+            entry(&crate::ID, &fixture.batch_accounts(deny_entry), &data),
+            Err(Error::from(CustomError::Denied).into())
+        );
+
+        // Its deny entry can't be swapped for another wallet's.
+        let deny_entry = wallet_info(DenyEntry::address(&Pubkey::new_unique()).0, false);
+        assert_eq!(
+            entry(&crate::ID, &fixture.batch_accounts(deny_entry), &data),
+            Err(Error::from(CustomError::InvalidRemainingAccounts).into())
+        );
+
+        // Users without a deny entry pass, and are skipped here as they have
+        // nothing left to claim.
+        let mut fixture = ClaimFixture::new();
+        let user_state = UserState {
+            user: fixture.user,
+            claimed: 10_000,
+            mint: fixture.mint,
+            ..Default::default()
+        };
+        fixture.accounts[ClaimFixture::USER_STATE] =
+            anchor_info(UserState::address(&fixture.user).0, &user_state);
+        let deny_entry = fixture.accounts[ClaimFixture::DENY_ENTRY].clone();
+        assert_eq!(
+            entry(&crate::ID, &fixture.batch_accounts(deny_entry), &data),
+            Ok(())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_close_state_vault() -> Result<()> {
+        install_stubs();
+        let data = instruction::CloseState {}.data();
+        let fixture = ClaimFixture::new();
+        let state_address = fixture.accounts[0].key();
+        let vault_authority = fixture.accounts[ClaimFixture::VAULT_AUTHORITY].key();
+        let vault_address = fixture.accounts[ClaimFixture::VAULT].key();
+        // Close accounts of the fixture's deployment, fully claimed and owned
+        // by its wallet.
+        let close = |vault: AccountInfo<'static>, token_program: Pubkey, outstanding: u64| {
+            let state = State {
+                owner: fixture.wallet,
+                outstanding,
+                ..fixture.state.clone()
+            };
+            vec![
+                anchor_info(state_address, &state),
+                wallet_info(fixture.wallet, true),
+                fixture.accounts[ClaimFixture::VAULT_AUTHORITY].clone(),
+                fixture.accounts[ClaimFixture::MINT].clone(),
+                vault,
+                program_info(token_program),
+            ]
+        };
+        let empty_vault = || token_account_info(vault_address, fixture.mint, vault_authority, 0);
+
+        // The other token program derives a vault address that holds nothing,
+        // so it is refused rather than letting a funded vault pass as empty.
+        let funded_vault = token_account_info(vault_address, fixture.mint, vault_authority, 10_000);
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &close(funded_vault, spl_token_2022::ID, 0),
+                &data
+            ),
+            Err(Error::from(ErrorCode::ConstraintAssociatedTokenTokenProgram).into())
+        );
+
+        // The vault must be the associated token account itself.
+        let other_vault =
+            token_account_info(Pubkey::new_unique(), fixture.mint, vault_authority, 0);
+        assert_eq!(
+            entry(&crate::ID, &close(other_vault, spl_token::ID, 0), &data),
+            Err(Error::from(ErrorCode::ConstraintAddress).into())
+        );
+
+        // Tokens left in the vault or allocations left to claim refuse the
+        // close.
+        let funded_vault = token_account_info(vault_address, fixture.mint, vault_authority, 1);
+        assert_eq!(
+            entry(&crate::ID, &close(funded_vault, spl_token::ID, 0), &data),
+            Err(Error::from(CustomError::VaultNotEmpty).into())
+        );
+        assert_eq!(
+            entry(&crate::ID, &close(empty_vault(), spl_token::ID, 1), &data),
+            Err(Error::from(CustomError::AllocationNotClaimed).into())
+        );
+
+        // An empty vault of the mint's own token program closes the state.
+        let accounts = close(empty_vault(), spl_token::ID, 0);
+        assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+        assert_eq!(accounts[0].lamports(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_vault() -> Result<()> {
+        install_stubs();
+
+        // Claims are paid from the vault's associated token account only,
+        // not from any other token account of the vault authority.
+        let mut fixture = ClaimFixture::new();
+        let vault_authority = fixture.accounts[ClaimFixture::VAULT_AUTHORITY].key();
+        fixture.accounts[ClaimFixture::VAULT] =
+            token_account_info(Pubkey::new_unique(), fixture.mint, vault_authority, 10_000);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(ErrorCode::ConstraintAddress.into())
+        );
+
+        // Nor from the associated token account of the other token program.
+        let mut fixture = ClaimFixture::new();
+        fixture.accounts[ClaimFixture::TOKEN_PROGRAM] = program_info(spl_token_2022::ID);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(ErrorCode::ConstraintAddress.into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_native() -> Result<()> {
+        install_stubs();
+        let mut fixture = ClaimFixture::with_mint(native_mint::ID);
+        let (native, _bump) = Pubkey::find_program_address(
+            &[SEED_PREFIX_NATIVE, fixture.wallet.as_ref()],
+            &crate::ID,
+        );
+        let wallet = fixture.accounts[ClaimFixture::CLAIMER].clone();
+        let user = fixture.accounts[1].clone();
+        let user_lamports = user.lamports();
+        let mint = fixture.accounts[ClaimFixture::MINT].clone();
+        fixture.accounts.extend([
+            wallet.clone(),
+            mint,
+            account_info(native, false, 0, Vec::new(), Pubkey::default()),
+            program_info(spl_token::ID),
+            program_info(system_program::ID),
+        ]);
+        let data = instruction::ClaimNative { amount: 4_000 }.data();
+
+        // The wallet receives the claim as lamports. The temporary account's
+        // rent comes back with it.
+        let wallet_lamports = wallet.lamports();
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &data), Ok(()));
+        assert_eq!(wallet.lamports(), wallet_lamports + 4_000);
+        let vault = &fixture.accounts[ClaimFixture::VAULT];
+        assert_eq!(
+            InterfaceAccount::<TokenAccount>::try_from(vault)?.amount,
+            6_000
+        );
+
+        // The temporary account is gone, and the wallet's own wrapped SOL
+        // account kept its balance and stays open.
+        let native = &fixture.accounts[fixture.accounts.len() - 3];
+        assert_eq!(native.lamports(), 0);
+        assert_eq!(*native.owner, system_program::ID);
+        assert_eq!(user.lamports(), user_lamports);
+        assert_eq!(InterfaceAccount::<TokenAccount>::try_from(&user)?.amount, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_message_alive() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            ..Default::default()
+        };
+        let address = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &crate::ID).0;
+        let wormhole_address = |seeds: &[&[u8]]| {
+            Pubkey::find_program_address(seeds, &wormhole::program::Wormhole::id()).0
+        };
+        let emitter = address(&[WormholeEmitter::SEED_PREFIX]);
+        let empty = |key| account_info(key, false, 0, Vec::new(), system_program::ID);
+        let message = |sequence: u64| empty(address(&[SEED_PREFIX_SENT, &sequence.to_le_bytes()]));
+        let fee = 100;
+        let collected = 1_000_000;
+        // Accounts of `initialize_wormhole` signed by `authority`, against a
+        // bridge charging `fee` per message.
+        let initialize = |authority: Pubkey| {
+            let bridge = wormhole::BridgeData {
+                last_lamports: collected,
+                config: wormhole::BridgeConfig {
+                    fee,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            vec![
+                wallet_info(authority, true),
+                anchor_info(address(&[State::SEED_PREFIX]), &state),
+                empty(address(&[Config::SEED_PREFIX])),
+                program_info(wormhole::program::Wormhole::id()),
+                account_info(
+                    wormhole_address(&[wormhole::BridgeData::SEED_PREFIX]),
+                    false,
+                    1_000_000,
+                    bridge.try_to_vec().unwrap(),
+                    wormhole::program::Wormhole::id(),
+                ),
+                account_info(
+                    wormhole_address(&[wormhole::FeeCollector::SEED_PREFIX]),
+                    false,
+                    collected,
+                    Vec::new(),
+                    wormhole::program::Wormhole::id(),
+                ),
+                empty(emitter),
+                empty(wormhole_address(&[
+                    wormhole::SequenceTracker::SEED_PREFIX,
+                    emitter.as_ref(),
+                ])),
+                message(wormhole::INITIAL_SEQUENCE),
+                sysvar_info(&Clock::default()),
+                sysvar_info(&Rent::default()),
+                program_info(system_program::ID),
+            ]
+        };
+        let posted = |info: &AccountInfo| {
+            let posted = wormhole::PostedVaa::<HelloWorldMessage>::try_deserialize_unchecked(
+                &mut &info.data.borrow()[..],
+            )
+            .unwrap();
+            assert_eq!(posted.emitter_address(), &emitter.to_bytes());
+            match posted.data() {
+                HelloWorldMessage::Alive {
+                    version,
+                    program_id,
+                } => {
+                    assert_eq!(*version, ALIVE_VERSION);
+                    (posted.sequence(), *program_id)
+                }
+                _ => panic!("posted message is not a heartbeat"),
+            }
+        };
+        let data = instruction::InitializeWormhole {}.data();
+
+        // Only the state's owner sets up Wormhole.
+        assert_eq!(
+            entry(&crate::ID, &initialize(Pubkey::new_unique()), &data),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+
+        // Setting up posts the first heartbeat, paying the fee.
+        let accounts = initialize(owner);
+        assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+        let config = Account::<Config>::try_from(&accounts[2])?;
+        assert_eq!(config.wormhole.bridge, accounts[4].key());
+        assert_eq!(config.wormhole.fee_collector, accounts[5].key());
+        assert_eq!(config.wormhole.sequence, accounts[7].key());
+        assert_eq!(posted(&accounts[8]), (0, crate::ID));
+        assert_eq!(accounts[5].lamports(), collected + fee);
+
+        // Then anyone posts the next ones, each into the message account of
+        // the emitter's next sequence.
+        let payer = wallet_info(Pubkey::new_unique(), true);
+        let next = message(wormhole::INITIAL_SEQUENCE + 1);
+        let send = [
+            payer.clone(),
+            accounts[2].clone(),
+            accounts[3].clone(),
+            accounts[4].clone(),
+            accounts[5].clone(),
+            accounts[6].clone(),
+            accounts[7].clone(),
+            next.clone(),
+            accounts[11].clone(),
+            accounts[9].clone(),
+            accounts[10].clone(),
+        ];
+        let payer_lamports = payer.lamports();
+        assert_eq!(
+            entry(&crate::ID, &send, &instruction::SendMessageAlive {}.data()),
+            Ok(())
+        );
+        assert_eq!(posted(&next), (1, crate::ID));
+        assert_eq!(accounts[5].lamports(), collected + 2 * fee);
+        assert_eq!(payer.lamports(), payer_lamports - fee - next.lamports());
+
+        // A stale message account is refused.
+        let send = [
+            &send[..7],
+            &[message(wormhole::INITIAL_SEQUENCE + 1)],
+            &send[8..],
+        ]
+        .concat();
+        assert_eq!(
+            entry(&crate::ID, &send, &instruction::SendMessageAlive {}.data()),
+            Err(Error::from(ErrorCode::ConstraintSeeds).into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_message_relayer_stake() -> Result<()> {
+        install_stubs();
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(Pubkey::new_unique(), 10_000)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            min_relayer_stake: 1_000,
+            ..Default::default()
+        };
+
+        // Relayers without a stake, or with less than the minimum staked, are
+        // refused before anything is credited.
+        let fixture = ReceiveFixture::new(&message, &state);
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(HelloWorldError::InsufficientRelayerStake).into())
+        );
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture.stake(999);
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(HelloWorldError::InsufficientRelayerStake).into())
+        );
+        assert!(
+            fixture.accounts[ReceiveFixture::USER_STATES].data_is_empty(),
+            "refused message credited"
+        );
+
+        // A sufficient stake lets the message through.
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture.stake(1_000);
+        assert_eq!(fixture.receive(), Ok(()));
+        assert_eq!(fixture.user_state(0).amount, 10_000);
+
+        // Without a minimum, no stake is needed.
+        let state = State {
+            min_relayer_stake: 0,
+            ..state
+        };
+        assert_eq!(ReceiveFixture::new(&message, &state).receive(), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_min_relayer_stake() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            operator: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let data = instruction::SetMinRelayerStake {
+            min_relayer_stake: 1_000,
+        }
+        .data();
+
+        // Only the owner of the state sets the minimum, in the state itself.
+        for authority in [state.operator, Pubkey::new_unique()] {
+            let accounts = [wallet_info(authority, true), state_info(&state)];
+            assert_eq!(
+                entry(&crate::ID, &accounts, &data),
+                Err(Error::from(CustomError::InvalidOwner).into())
+            );
+        }
+        let accounts = [wallet_info(owner, true), state_info(&state)];
+        assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+        assert_eq!(
+            Account::<State>::try_from(&accounts[1])?.min_relayer_stake,
+            1_000
+        );
+
+        // So does slashing, which pays the owner out of the relayer's stake.
+        let relayer = Pubkey::new_unique();
+        let stake = RelayerStake {
+            relayer,
+            amount: 1_000,
+        };
+        let stake_address = Pubkey::find_program_address(
+            &[RelayerStake::SEED_PREFIX, relayer.as_ref()],
+            &crate::ID,
+        )
+        .0;
+        let slash = instruction::SlashRelayer {
+            _relayer: relayer,
+            amount: 400,
+        }
+        .data();
+        let accounts = [
+            wallet_info(state.operator, true),
+            state_info(&state),
+            anchor_info(stake_address, &stake),
+        ];
+        assert_eq!(
+            entry(&crate::ID, &accounts, &slash),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+        let accounts = [
+            wallet_info(owner, true),
+            state_info(&state),
+            anchor_info(stake_address, &stake),
+        ];
+        let (owner_lamports, stake_lamports) = (accounts[0].lamports(), accounts[2].lamports());
+        assert_eq!(entry(&crate::ID, &accounts, &slash), Ok(()));
+        assert_eq!(accounts[0].lamports(), owner_lamports + 400);
+        assert_eq!(accounts[2].lamports(), stake_lamports - 400);
+        assert_eq!(Account::<RelayerStake>::try_from(&accounts[2])?.amount, 600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_message() -> Result<()> {
+        install_stubs();
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Amounts with 8 decimals, credited in the mint's 6.
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(alice, 1_000_000), (bob, 250), (alice, 100)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            outstanding: 500,
+            total_received: 500,
+            ..Default::default()
+        };
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        // Bob already has an allocation, alice gets her user state created.
+        let bob_state = UserState {
+            user: bob,
+            amount: 500,
+            mint: state.allowed_mint,
+            ..Default::default()
+        };
+        fixture.accounts[ReceiveFixture::USER_STATES + 1] = sized_anchor_info(
+            UserState::address(&bob).0,
+            &bob_state,
+            UserState::MAXIMUM_SIZE,
+        );
+
+        // Every entry is credited in its user state, a repeated user on top
+        // of its first entry, and the truncated fraction kept as dust.
+        assert_eq!(fixture.receive(), Ok(()));
+        let alice_state = fixture.user_state(0);
+        assert_eq!(alice_state.user, alice);
+        assert_eq!(alice_state.amount, 10_000 + 1);
+        let bob_state = fixture.user_state(1);
+        assert_eq!(bob_state.amount, 500 + 2);
+        assert_eq!(bob_state.dust, UserState::DUST_PRECISION / 2);
+        assert_eq!(fixture.user_state(2).amount, alice_state.amount);
+        let state = Account::<State>::try_from(&fixture.accounts[ReceiveFixture::STATE])?;
+        assert_eq!(state.total_received, 500 + 10_003);
+        // The event lists what each entry was credited, in the mint's
+        // decimals.
+        let event = logged_events::<MessageReceived>().pop().unwrap();
+        assert_eq!(event.vaa_hash, fixture.vaa_hash);
+        assert_eq!(event.users, [alice, bob, alice]);
+        assert_eq!(event.amounts, [10_000, 2, 1]);
+        assert_eq!(state.outstanding, 500 + 10_003);
+
+        // The message is recorded and its sequence consumed.
+        let received = Account::<Received>::try_from(&fixture.accounts[ReceiveFixture::RECEIVED])?;
+        assert_eq!(received.wormhole_message_hash, fixture.vaa_hash);
+        let foreign_emitter = Account::<ForeignEmitter>::try_from(
+            &fixture.accounts[ReceiveFixture::FOREIGN_EMITTER],
+        )?;
+        assert_eq!(
+            foreign_emitter.last_sequence,
+            Some(ReceiveFixture::SEQUENCE)
+        );
+
+        // So relaying it again credits nothing, as its received account
+        // already exists.
+        assert_eq!(
+            fixture.receive(),
+            Err(ProgramError::Custom(
+                SystemError::AccountAlreadyInUse as u32
+            ))
+        );
+        assert_eq!(fixture.user_state(0).amount, alice_state.amount);
+        assert_eq!(fixture.user_state(1).amount, bob_state.amount);
+
+        // Each entry needs its user state account, in order.
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture.accounts.pop();
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(CustomError::InvalidRemainingAccounts).into())
+        );
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture
+            .accounts
+            .swap(ReceiveFixture::USER_STATES, ReceiveFixture::USER_STATES + 1);
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(CustomError::InvalidRemainingAccounts).into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receive_message_unregistered_emitter() {
+        install_stubs();
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(Pubkey::new_unique(), 100)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+
+        // A message posted by another emitter on the registered chain is
+        // refused.
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        let registered = ForeignEmitter {
+            chain: ReceiveFixture::CHAIN,
+            address: [2; 32],
+            last_sequence: None,
+        };
+        let address = fixture.accounts[ReceiveFixture::FOREIGN_EMITTER].key();
+        fixture.accounts[ReceiveFixture::FOREIGN_EMITTER] =
+            sized_anchor_info(address, &registered, ForeignEmitter::MAXIMUM_SIZE);
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(HelloWorldError::InvalidForeignEmitter).into())
+        );
+
+        // As is one from a chain with no registered emitter.
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture.accounts[ReceiveFixture::FOREIGN_EMITTER] =
+            account_info(address, false, 0, Vec::new(), system_program::ID);
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(ErrorCode::AccountNotInitialized).into())
+        );
+        assert!(fixture.accounts[ReceiveFixture::USER_STATES].data_is_empty());
+    }
+
+    #[test]
+    fn test_receive_message_sequential() -> Result<()> {
+        install_stubs();
+        let alice = Pubkey::new_unique();
+        let credit = |amount| HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(alice, amount)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let first = ReceiveFixture::new(&credit(100), &state);
+        assert_eq!(first.receive(), Ok(()));
+
+        // A second message crediting alice, relayed against the accounts the
+        // first one wrote, adds to her allocation.
+        let mut second =
+            ReceiveFixture::with_sequence(&credit(250), &state, ReceiveFixture::SEQUENCE + 1);
+        for i in [
+            ReceiveFixture::FOREIGN_EMITTER,
+            ReceiveFixture::STATE,
+            ReceiveFixture::USER_STATES,
+        ] {
+            second.accounts[i] = first.accounts[i].clone();
+        }
+        assert_eq!(second.receive(), Ok(()));
+        assert_eq!(second.user_state(0).amount, 100 + 250);
+
+        // Retrying either message credits nothing more.
+        assert_eq!(
+            first.receive(),
+            Err(ProgramError::Custom(
+                SystemError::AccountAlreadyInUse as u32
+            ))
+        );
+        assert_eq!(
+            second.receive(),
+            Err(ProgramError::Custom(
+                SystemError::AccountAlreadyInUse as u32
+            ))
+        );
+        assert_eq!(second.user_state(0).amount, 100 + 250);
+        let state = Account::<State>::try_from(&second.accounts[ReceiveFixture::STATE])?;
+        assert_eq!(state.total_received, 100 + 250);
+        assert_eq!(state.outstanding, 100 + 250);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_received() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            operator: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let (chain, sequence) = (2u16, 7u64);
+        let address = Pubkey::find_program_address(
+            &[
+                Received::SEED_PREFIX,
+                &chain.to_le_bytes(),
+                &sequence.to_le_bytes(),
+            ],
+            &crate::ID,
+        )
+        .0;
+        let received = Received {
+            batch_id: 7,
+            wormhole_message_hash: [1; 32],
+            message: b"All your base are belong to us".to_vec(),
+        };
+        // Accounts growing the received account of `sequence`, sized for an
+        // older, shorter layout.
+        let migrate = |authority: Pubkey| {
+            vec![
+                wallet_info(authority, true),
+                state_info(&state),
+                anchor_info(address, &received),
+                program_info(system_program::ID),
+            ]
+        };
+        let data = instruction::MigrateReceived {
+            _chain: chain,
+            _sequence: sequence,
+        }
+        .data();
+
+        // Only the owner of the state migrates accounts.
+        for authority in [state.operator, Pubkey::new_unique()] {
+            assert_eq!(
+                entry(&crate::ID, &migrate(authority), &data),
+                Err(Error::from(CustomError::InvalidOwner).into())
+            );
+        }
+
+        // The account grows to the current layout, keeping its fields, and
+        // the owner tops up its rent.
+        let accounts = migrate(owner);
+        let (owner_lamports, old_lamports) = (accounts[0].lamports(), accounts[2].lamports());
+        assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+        let info = &accounts[2];
+        assert_eq!(info.data_len(), Received::MAXIMUM_SIZE);
+        let new_lamports = Rent::default().minimum_balance(Received::MAXIMUM_SIZE);
+        assert_eq!(info.lamports(), new_lamports);
+        assert_eq!(
+            accounts[0].lamports(),
+            owner_lamports - (new_lamports - old_lamports)
+        );
+        let migrated = Account::<Received>::try_from(info)?;
+        assert_eq!(migrated.batch_id, received.batch_id);
+        assert_eq!(
+            migrated.wormhole_message_hash,
+            received.wormhole_message_hash
+        );
+        assert_eq!(migrated.message, received.message);
+
+        // Migrating it again changes nothing.
+        let owner_lamports = accounts[0].lamports();
+        assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+        assert_eq!(info.data_len(), Received::MAXIMUM_SIZE);
+        assert_eq!(accounts[0].lamports(), owner_lamports);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_emitter_unauthorized() -> Result<()> {
+        install_stubs();
+        let state = State {
+            owner: Pubkey::new_unique(),
+            operator: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        // An EVM emitter, left-padded to 32 bytes.
+        let chain = 2u16;
+        let mut address = [3u8; 32];
+        address[..12].fill(0);
+        let foreign_emitter = Pubkey::find_program_address(
+            &[ForeignEmitter::SEED_PREFIX, &chain.to_le_bytes()[..]],
+            &crate::ID,
+        )
+        .0;
+        // Accounts registering a first emitter for `chain`.
+        let register = |authority: Pubkey| {
+            vec![
+                wallet_info(authority, true),
+                state_info(&state),
+                account_info(foreign_emitter, false, 0, Vec::new(), system_program::ID),
+                program_info(system_program::ID),
+            ]
+        };
+        let data = instruction::RegisterEmitter { chain, address }.data();
+
+        // Neither a stranger nor the state account itself registers emitters.
+        for authority in [Pubkey::new_unique(), state_info(&state).key()] {
+            assert_eq!(
+                entry(&crate::ID, &register(authority), &data),
+                Err(Error::from(CustomError::InvalidOwner).into())
+            );
+        }
+
+        // The owner does.
+        let accounts = register(state.owner);
+        assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+        let registered = Account::<ForeignEmitter>::try_from(&accounts[2])?;
+        assert_eq!(registered.chain, chain);
+        assert_eq!(registered.address, address);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pause_claims() -> Result<()> {
+        install_stubs();
+        let mut fixture = ClaimFixture::new();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            ..fixture.state.clone()
+        };
+        fixture.accounts[0] = anchor_info(fixture.accounts[0].key(), &state);
+        let set_paused = |paused: bool| {
+            let accounts = [fixture.accounts[0].clone(), wallet_info(owner, true)];
+            entry(
+                &crate::ID,
+                &accounts,
+                &instruction::SetPaused { paused }.data(),
+            )
+        };
+        let claim = instruction::ClaimToken { amount: 4_000 }.data();
+        let batch = instruction::BatchClaim {}.data();
+        let batch_accounts =
+            fixture.batch_accounts(fixture.accounts[ClaimFixture::DENY_ENTRY].clone());
+        let amounts = || {
+            let user = InterfaceAccount::<TokenAccount>::try_from(&fixture.accounts[1]).unwrap();
+            let user_state =
+                Account::<UserState>::try_from(&fixture.accounts[ClaimFixture::USER_STATE])
+                    .unwrap();
+            (user.amount, user_state.amount)
+        };
+
+        // While paused, neither single nor batch claims pay anything.
+        assert_eq!(set_paused(true), Ok(()));
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::Paused).into())
+        );
+        assert_eq!(
+            entry(&crate::ID, &batch_accounts, &batch),
+            Err(Error::from(CustomError::Paused).into())
+        );
+        assert_eq!(amounts(), (0, 10_000));
+
+        // Once unpaused, both go through again.
+        assert_eq!(set_paused(false), Ok(()));
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(amounts(), (4_000, 6_000));
+        assert_eq!(entry(&crate::ID, &batch_accounts, &batch), Ok(()));
+        assert_eq!(amounts(), (10_000, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_underfunded_vault() -> Result<()> {
+        install_stubs();
+        let mut fixture = ClaimFixture::new();
+        let vault = fixture.accounts[ClaimFixture::VAULT].key();
+        let vault_authority = fixture.accounts[ClaimFixture::VAULT_AUTHORITY].key();
+        fixture.accounts[ClaimFixture::VAULT] =
+            token_account_info(vault, fixture.mint, vault_authority, 1_000);
+        let data = instruction::ClaimToken { amount: 4_000 }.data();
+
+        // The transfer fails, and the allocation debited before it is left as
+        // it was.
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &data),
+            Err(ProgramError::Custom(
+                spl_token::error::TokenError::InsufficientFunds as u32
+            ))
+        );
+        let user_state =
+            Account::<UserState>::try_from(&fixture.accounts[ClaimFixture::USER_STATE])?;
+        assert_eq!(user_state.amount, 10_000);
+        assert_eq!(user_state.claimed, 0);
+        assert_eq!(
+            InterfaceAccount::<TokenAccount>::try_from(&fixture.accounts[ClaimFixture::VAULT])?
+                .amount,
+            1_000
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_state_mint() -> Result<()> {
+        install_stubs();
+        let user = Pubkey::new_unique();
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(user, 1_000)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+
+        // A new allocation is denominated in the mint it is credited in.
+        let fixture = ReceiveFixture::new(&message, &state);
+        assert_eq!(fixture.receive(), Ok(()));
+        assert_eq!(fixture.user_state(0).mint, state.allowed_mint);
+
+        // An allocation in another mint isn't credited on top.
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        let other_mint = UserState {
+            user,
+            amount: 500,
+            mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        fixture.accounts[ReceiveFixture::USER_STATES] = sized_anchor_info(
+            UserState::address(&user).0,
+            &other_mint,
+            UserState::MAXIMUM_SIZE,
+        );
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(CustomError::MintMismatch).into())
+        );
+
+        // Nor claimed from a vault of another mint, alone or in a batch.
+        let mut fixture = ClaimFixture::new();
+        let other_mint = UserState {
+            user: fixture.user,
+            amount: 10_000,
+            mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        fixture.accounts[ClaimFixture::USER_STATE] =
+            anchor_info(UserState::address(&fixture.user).0, &other_mint);
+        assert_eq!(
+            fixture.try_accounts().err(),
+            Some(CustomError::MintMismatch.into())
+        );
+        let deny_entry = fixture.accounts[ClaimFixture::DENY_ENTRY].clone();
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &fixture.batch_accounts(deny_entry),
+                &instruction::BatchClaim {}.data()
+            ),
+            Err(Error::from(CustomError::MintMismatch).into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_state_expiry() -> Result<()> {
+        install_stubs();
+        let expiry = 1_700_000_000;
+
+        // The message sets the expiry of the allocations it credits.
+        let user = Pubkey::new_unique();
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(user, 1_000)],
+            delegates: vec![Pubkey::default()],
+            expiries: vec![expiry],
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let fixture = ReceiveFixture::new(&message, &state);
+        assert_eq!(fixture.receive(), Ok(()));
+        assert_eq!(fixture.user_state(0).expiry, expiry);
+
+        let mut fixture = ClaimFixture::new();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            ..fixture.state.clone()
+        };
+        fixture.accounts[0] = anchor_info(fixture.accounts[0].key(), &state);
+        let user_state = UserState {
+            user: fixture.user,
+            amount: 10_000,
+            mint: fixture.mint,
+            expiry,
+            ..Default::default()
+        };
+        fixture.accounts[ClaimFixture::USER_STATE] =
+            anchor_info(UserState::address(&fixture.user).0, &user_state);
+        let claim = instruction::ClaimToken { amount: 4_000 }.data();
+        let deny_entry = fixture.accounts[ClaimFixture::DENY_ENTRY].clone();
+        let batch_accounts = fixture.batch_accounts(deny_entry);
+        let sweep_accounts = [
+            fixture.accounts[0].clone(),
+            wallet_info(owner, true),
+            fixture.accounts[ClaimFixture::USER_STATE].clone(),
+        ];
+        let sweep = instruction::SweepExpired {}.data();
+
+        // Claims go through up to the expiry, and the allocation can't be
+        // swept yet.
+        set_clock(expiry);
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(
+            entry(&crate::ID, &sweep_accounts, &sweep),
+            Err(Error::from(CustomError::ClaimNotExpired).into())
+        );
+
+        // Past it, claims are refused and batches skip the allocation.
+        set_clock(expiry + 1);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::ClaimExpired).into())
+        );
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &batch_accounts,
+                &instruction::BatchClaim {}.data()
+            ),
+            Ok(())
+        );
+        let user_state =
+            Account::<UserState>::try_from(&fixture.accounts[ClaimFixture::USER_STATE])?;
+        assert_eq!(user_state.amount, 6_000);
+
+        // The owner sweeps what is left, closing the user state.
+        let owner_lamports = sweep_accounts[1].lamports();
+        let rent = sweep_accounts[2].lamports();
+        assert_eq!(entry(&crate::ID, &sweep_accounts, &sweep), Ok(()));
+        assert_eq!(sweep_accounts[1].lamports(), owner_lamports + rent);
+        assert_eq!(sweep_accounts[2].lamports(), 0);
+        let state = Account::<State>::try_from(&fixture.accounts[0])?;
+        assert_eq!(state.outstanding, 0);
+
+        set_clock(0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_user_expiry() -> Result<()> {
+        install_stubs();
+        let mut fixture = ClaimFixture::new();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            ..fixture.state.clone()
+        };
+        fixture.accounts[0] = anchor_info(fixture.accounts[0].key(), &state);
+        let expiry = 1_700_000_000;
+        let set_expiry = |authority: Pubkey| {
+            let accounts = [
+                fixture.accounts[0].clone(),
+                wallet_info(authority, true),
+                fixture.accounts[ClaimFixture::USER_STATE].clone(),
+            ];
+            entry(
+                &crate::ID,
+                &accounts,
+                &instruction::SetUserExpiry { expiry }.data(),
+            )
+        };
+
+        // Only the owner sets expiries.
+        assert_eq!(
+            set_expiry(Pubkey::new_unique()),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+        assert_eq!(set_expiry(owner), Ok(()));
+        let user_state =
+            Account::<UserState>::try_from(&fixture.accounts[ClaimFixture::USER_STATE])?;
+        assert_eq!(user_state.expiry, expiry);
+
+        // Claims are refused once it has passed.
+        set_clock(expiry + 1);
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &fixture.accounts,
+                &instruction::ClaimToken { amount: 4_000 }.data()
+            ),
+            Err(Error::from(CustomError::ClaimExpired).into())
+        );
+
+        set_clock(0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee_discount() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        // Has the owner give the fixture's user `fee_discount_bps` off a fee of
+        // 100 bps, then claims 4_000. Returns the fee and what the user
+        // received.
+        let claim = |fee_discount_bps: u16| {
+            let mut fixture = ClaimFixture::new();
+            let fee_account = Pubkey::new_unique();
+            let state = State {
+                owner,
+                fee_bps: 100,
+                fee_recipient: fee_account,
+                ..fixture.state.clone()
+            };
+            fixture.accounts[0] = anchor_info(fixture.accounts[0].key(), &state);
+            fixture.accounts[ClaimFixture::FEE_ACCOUNT] =
+                token_account_info(fee_account, fixture.mint, owner, 0);
+            let accounts = [
+                fixture.accounts[0].clone(),
+                wallet_info(owner, true),
+                fixture.accounts[ClaimFixture::USER_STATE].clone(),
+            ];
+            let data = instruction::SetFeeDiscount { fee_discount_bps }.data();
+            assert_eq!(entry(&crate::ID, &accounts, &data), Ok(()));
+
+            let data = instruction::ClaimToken { amount: 4_000 }.data();
+            assert_eq!(entry(&crate::ID, &fixture.accounts, &data), Ok(()));
+            let balance = |i: usize| {
+                InterfaceAccount::<TokenAccount>::try_from(&fixture.accounts[i])
+                    .unwrap()
+                    .amount
+            };
+            (balance(ClaimFixture::FEE_ACCOUNT), balance(1))
+        };
+
+        // The same claim pays a lower fee with a discount, and none once the
+        // discount covers the whole fee.
+        assert_eq!(claim(0), (40, 3_960));
+        assert_eq!(claim(60), (16, 3_984));
+        assert_eq!(claim(100), (0, 4_000));
+        assert_eq!(claim(500), (0, 4_000));
+
+        // Only the owner grants discounts, and none above 100%.
+        let fixture = ClaimFixture::new();
+        let state = State {
+            owner,
+            ..fixture.state.clone()
+        };
+        let set_discount = |authority: Pubkey, fee_discount_bps: u16| {
+            let accounts = [
+                anchor_info(fixture.accounts[0].key(), &state),
+                wallet_info(authority, true),
+                fixture.accounts[ClaimFixture::USER_STATE].clone(),
+            ];
+            entry(
+                &crate::ID,
+                &accounts,
+                &instruction::SetFeeDiscount { fee_discount_bps }.data(),
+            )
+        };
+        assert_eq!(
+            set_discount(Pubkey::new_unique(), 60),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+        assert_eq!(
+            set_discount(owner, MAX_FEE_BPS + 1),
+            Err(Error::from(CustomError::InvalidFee).into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_test_allocations() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            allowed_mint: Pubkey::new_unique(),
+            outstanding: 500,
+            ..Default::default()
+        };
+        let state_account = state_info(&state);
+        let canary = Pubkey::new_unique();
+        let canary_state = account_info(
+            UserState::address(&canary).0,
+            false,
+            0,
+            Vec::new(),
+            system_program::ID,
+        );
+        let inject = |authority: Pubkey, user: Pubkey, user_state: &AccountInfo<'static>| {
+            let accounts = [
+                wallet_info(authority, true),
+                state_account.clone(),
+                user_state.clone(),
+                program_info(system_program::ID),
+            ];
+            entry(
+                &crate::ID,
+                &accounts,
+                &instruction::InjectTestAllocation { user, amount: 100 }.data(),
+            )
+        };
+
+        // Only the owner injects test allocations.
+        assert_eq!(
+            inject(Pubkey::new_unique(), canary, &canary_state),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+
+        // Injections create the user state, flagged as a test, and add up.
+        assert_eq!(inject(owner, canary, &canary_state), Ok(()));
+        assert_eq!(inject(owner, canary, &canary_state), Ok(()));
+        let injected = Account::<UserState>::try_from(&canary_state)?;
+        assert!(injected.is_test, "test allocation not flagged");
+        assert_eq!(injected.user, canary);
+        assert_eq!(injected.mint, state.allowed_mint);
+        assert_eq!(injected.amount, 200);
+        let outstanding = Account::<State>::try_from(&state_account)?.outstanding;
+        assert_eq!(outstanding, 500 + 200);
+
+        // Real allocations are never flagged.
+        let user = Pubkey::new_unique();
+        let user_state = anchor_info(
+            UserState::address(&user).0,
+            &UserState {
+                user,
+                amount: 500,
+                mint: state.allowed_mint,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            inject(owner, user, &user_state),
+            Err(Error::from(CustomError::NotTestAllocation).into())
+        );
+        assert!(!Account::<UserState>::try_from(&user_state)?.is_test);
+
+        // Messages can't credit a test allocation while it exists.
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(canary, 100)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture.accounts[ReceiveFixture::USER_STATES] = canary_state.clone();
+        assert_eq!(
+            fixture.receive(),
+            Err(Error::from(CustomError::TestAllocation).into())
+        );
+
+        // Only the owner purges, and only test allocations.
+        let purge = |accounts: &[AccountInfo<'static>]| {
+            entry(
+                &crate::ID,
+                accounts,
+                &instruction::PurgeTestAllocations {}.data(),
+            )
+        };
+        assert_eq!(
+            purge(&[
+                state_account.clone(),
+                wallet_info(Pubkey::new_unique(), true),
+                canary_state.clone(),
+            ]),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+        let owner_info = wallet_info(owner, true);
+        assert_eq!(
+            purge(&[
+                state_account.clone(),
+                owner_info.clone(),
+                user_state.clone(),
+                canary_state.clone(),
+            ]),
+            Err(Error::from(CustomError::NotTestAllocation).into())
+        );
+
+        // Purging closes the user state and drops its allocation.
+        let (owner_lamports, rent) = (owner_info.lamports(), canary_state.lamports());
+        assert_eq!(
+            purge(&[
+                state_account.clone(),
+                owner_info.clone(),
+                canary_state.clone(),
+            ]),
+            Ok(())
+        );
+        assert_eq!(owner_info.lamports(), owner_lamports + rent);
+        assert_eq!(canary_state.lamports(), 0);
+        let outstanding = Account::<State>::try_from(&state_account)?.outstanding;
+        assert_eq!(outstanding, 500);
+
+        // After which messages credit the user as usual.
+        let mut fixture = ReceiveFixture::new(&message, &state);
+        fixture.accounts[ReceiveFixture::USER_STATES] = canary_state.clone();
+        assert_eq!(fixture.receive(), Ok(()));
+        let user_state = fixture.user_state(0);
+        assert!(!user_state.is_test, "credit flagged as a test");
+        assert_eq!(user_state.amount, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_review_threshold() -> Result<()> {
+        install_stubs();
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(alice, 1_000), (bob, 1_001)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: Pubkey::new_unique(),
+            review_threshold: 1_000,
+            ..Default::default()
+        };
+
+        // Only allocations credited above the threshold are held for review.
+        let fixture = ReceiveFixture::new(&message, &state);
+        assert_eq!(fixture.receive(), Ok(()));
+        assert!(
+            !fixture.user_state(0).pending_review,
+            "small allocation held"
+        );
+        assert!(
+            fixture.user_state(1).pending_review,
+            "large allocation paid"
+        );
+
+        // Claims of allocations below it settle right away.
+        let mut fixture = ClaimFixture::new();
+        let owner = Pubkey::new_unique();
+        let state = State {
+            owner,
+            ..fixture.state.clone()
+        };
+        fixture.accounts[0] = anchor_info(fixture.accounts[0].key(), &state);
+        let claim = instruction::ClaimToken { amount: 4_000 }.data();
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+
+        // Those held for review are refused, alone or in a batch, until the
+        // owner approves them.
+        let user_state = UserState {
+            pending_review: true,
+            ..Account::<UserState>::try_from(&fixture.accounts[ClaimFixture::USER_STATE])?
+                .into_inner()
+        };
+        fixture.accounts[ClaimFixture::USER_STATE] =
+            anchor_info(UserState::address(&fixture.user).0, &user_state);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::PendingReview).into())
+        );
+        let deny_entry = fixture.accounts[ClaimFixture::DENY_ENTRY].clone();
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &fixture.batch_accounts(deny_entry),
+                &instruction::BatchClaim {}.data()
+            ),
+            Ok(())
+        );
+        let approve = |authority: Pubkey| {
+            let accounts = [
+                fixture.accounts[0].clone(),
+                wallet_info(authority, true),
+                fixture.accounts[ClaimFixture::USER_STATE].clone(),
+            ];
+            entry(&crate::ID, &accounts, &instruction::ApproveClaim {}.data())
+        };
+        assert_eq!(
+            approve(Pubkey::new_unique()),
+            Err(Error::from(CustomError::InvalidOwner).into())
+        );
+        assert_eq!(approve(owner), Ok(()));
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        let user_state =
+            Account::<UserState>::try_from(&fixture.accounts[ClaimFixture::USER_STATE])?;
+        assert!(!user_state.pending_review, "approval not recorded");
+        assert_eq!(user_state.amount, 10_000 - 2 * 4_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recipient() -> Result<()> {
+        install_stubs();
+        let mut fixture = ClaimFixture::new();
+        let recipient = Pubkey::new_unique();
+        let user = fixture.accounts[1].clone();
+        let user_state = fixture.accounts[ClaimFixture::USER_STATE].clone();
+        let set_recipient = |signer: Pubkey, recipient: Pubkey| {
+            let accounts = [user.clone(), wallet_info(signer, true), user_state.clone()];
+            entry(
+                &crate::ID,
+                &accounts,
+                &instruction::SetRecipient { recipient }.data(),
+            )
+        };
+        let claim = instruction::ClaimToken { amount: 4_000 }.data();
+        let balance = |info: &AccountInfo<'static>| {
+            InterfaceAccount::<TokenAccount>::try_from(info).map_or(0, |account| account.amount)
+        };
+
+        // Only the wallet owning the allocation's token account names a
+        // recipient.
+        assert_eq!(
+            set_recipient(Pubkey::new_unique(), recipient),
+            Err(Error::from(CustomError::InvalidUser).into())
+        );
+        assert_eq!(set_recipient(fixture.wallet, recipient), Ok(()));
+
+        // Its claims then need the recipient, who is paid instead of the user.
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::RecipientMismatch).into())
+        );
+        fixture.accounts[ClaimFixture::RECIPIENT] =
+            token_account_info(Pubkey::new_unique(), fixture.mint, Pubkey::new_unique(), 0);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::RecipientMismatch).into())
+        );
+        let recipient_account =
+            token_account_info(recipient, fixture.mint, Pubkey::new_unique(), 0);
+        fixture.accounts[ClaimFixture::RECIPIENT] = recipient_account.clone();
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(balance(&recipient_account), 4_000);
+        assert_eq!(balance(&user), 0);
+
+        // Batches, which carry no recipients, skip the allocation.
+        let deny_entry = fixture.accounts[ClaimFixture::DENY_ENTRY].clone();
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &fixture.batch_accounts(deny_entry),
+                &instruction::BatchClaim {}.data()
+            ),
+            Ok(())
+        );
+        assert_eq!(balance(&user), 0);
+
+        // Clearing the recipient pays the user again, and refuses recipients.
+        assert_eq!(set_recipient(fixture.wallet, Pubkey::default()), Ok(()));
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::RecipientMismatch).into())
+        );
+        fixture.accounts[ClaimFixture::RECIPIENT] = program_info(crate::ID);
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(balance(&user), 4_000);
+        assert_eq!(balance(&recipient_account), 4_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_correlation_id() -> Result<()> {
+        install_stubs();
+        let mut fixture = ClaimFixture::new();
+        let correlation_id = [4; 16];
+        let claim = instruction::ClaimToken { amount: 4_000 }.data();
+
+        // Claims of allocations credited without an id report none.
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        let event = logged_events::<TokenClaimed>().pop().unwrap();
+        assert_eq!(event.correlation_id, NO_CORRELATION_ID);
+
+        // The id a message credits an allocation with is reported by its
+        // claims.
+        let message = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 6,
+            entries: vec![(fixture.user, 10_000)],
+            delegates: vec![Pubkey::default()],
+            expiries: vec![0],
+            correlation_ids: vec![correlation_id],
+            referrers: Vec::new(),
+        };
+        let state = State {
+            allowed_mint: fixture.mint,
+            ..Default::default()
+        };
+        let receive = ReceiveFixture::new(&message, &state);
+        assert_eq!(receive.receive(), Ok(()));
+        assert_eq!(receive.user_state(0).correlation_id, correlation_id);
+        fixture.accounts[ClaimFixture::USER_STATE] =
+            receive.accounts[ReceiveFixture::USER_STATES].clone();
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        let event = logged_events::<TokenClaimed>().pop().unwrap();
+        assert_eq!(event.correlation_id, correlation_id);
+        assert_eq!(event.amount, 4_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_referral() -> Result<()> {
+        install_stubs();
+        let owner = Pubkey::new_unique();
+        let referrer = Pubkey::new_unique();
+        let claim = instruction::ClaimToken { amount: 4_000 }.data();
+        let balance = |info: &AccountInfo<'static>| {
+            InterfaceAccount::<TokenAccount>::try_from(info).map_or(0, |account| account.amount)
+        };
+        // A claim fixture paying referred claims a 5% cut, whose allocation
+        // was credited naming the referrer `referrer` picks for it.
+        let referred = |referrer: &dyn Fn(&ClaimFixture) -> Pubkey| {
+            let mut fixture = ClaimFixture::new();
+            let referrer = referrer(&fixture);
+            let state = State {
+                owner,
+                ..fixture.state.clone()
+            };
+            fixture.accounts[0] = anchor_info(fixture.accounts[0].key(), &state);
+            let set_referral = |signer: Pubkey, referral_bps: u16| {
+                let accounts = [fixture.accounts[0].clone(), wallet_info(signer, true)];
+                entry(
+                    &crate::ID,
+                    &accounts,
+                    &instruction::SetReferralBps { referral_bps }.data(),
+                )
+            };
+            assert_eq!(
+                set_referral(Pubkey::new_unique(), 500),
+                Err(Error::from(CustomError::InvalidOwner).into())
+            );
+            assert_eq!(
+                set_referral(owner, MAX_FEE_BPS + 1),
+                Err(Error::from(CustomError::InvalidFee).into())
+            );
+            assert_eq!(set_referral(owner, 500), Ok(()));
+
+            let message = HelloWorldMessage::MultiUserInfo {
+                source_decimals: 6,
+                entries: vec![(fixture.user, 10_000)],
+                delegates: vec![Pubkey::default()],
+                expiries: vec![0],
+                correlation_ids: vec![NO_CORRELATION_ID],
+                referrers: vec![referrer],
+            };
+            let state = State {
+                allowed_mint: fixture.mint,
+                ..Default::default()
+            };
+            let receive = ReceiveFixture::new(&message, &state);
+            assert_eq!(receive.receive(), Ok(()));
+            assert_eq!(receive.user_state(0).referrer, referrer);
+            fixture.accounts[ClaimFixture::USER_STATE] =
+                receive.accounts[ReceiveFixture::USER_STATES].clone();
+            fixture
+        };
+
+        // Allocations without a referrer pay none, and refuse referrers.
+        let mut fixture = referred(&|_| Pubkey::default());
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(balance(&fixture.accounts[1]), 4_000);
+        fixture.accounts[ClaimFixture::REFERRER] =
+            token_account_info(Pubkey::new_unique(), fixture.mint, referrer, 0);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::ReferrerMismatch).into())
+        );
+
+        // Referred claims need the referrer's token account, which is paid
+        // its cut of what the user receives.
+        let mut fixture = referred(&|_| referrer);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::ReferrerMismatch).into())
+        );
+        fixture.accounts[ClaimFixture::REFERRER] =
+            token_account_info(Pubkey::new_unique(), fixture.mint, Pubkey::new_unique(), 0);
+        assert_eq!(
+            entry(&crate::ID, &fixture.accounts, &claim),
+            Err(Error::from(CustomError::ReferrerMismatch).into())
+        );
+        let referrer_account = token_account_info(Pubkey::new_unique(), fixture.mint, referrer, 0);
+        fixture.accounts[ClaimFixture::REFERRER] = referrer_account.clone();
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(balance(&referrer_account), 200);
+        assert_eq!(balance(&fixture.accounts[1]), 3_800);
+        let event = logged_events::<TokenClaimed>().pop().unwrap();
+        assert_eq!(event.amount, 3_800);
+
+        // Batches, which carry no referrers, skip the allocation.
+        let deny_entry = fixture.accounts[ClaimFixture::DENY_ENTRY].clone();
+        assert_eq!(
+            entry(
+                &crate::ID,
+                &fixture.batch_accounts(deny_entry),
+                &instruction::BatchClaim {}.data()
+            ),
+            Ok(())
+        );
+        assert_eq!(balance(&fixture.accounts[1]), 3_800);
+
+        // As are users referring themselves, who keep the whole claim.
+        let fixture = referred(&|fixture| fixture.wallet);
+        assert_eq!(entry(&crate::ID, &fixture.accounts, &claim), Ok(()));
+        assert_eq!(balance(&fixture.accounts[1]), 4_000);
+
+        Ok(())
+    }
 }
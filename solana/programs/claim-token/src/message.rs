@@ -1,88 +1,208 @@
 use anchor_lang::{prelude::Pubkey, AnchorDeserialize, AnchorSerialize};
+use claim_token_message::{Message, Read};
 use std::io;
-use wormhole_io::Readable;
 
-const PAYLOAD_ID_ALIVE: u8 = 0;
-const PAYLOAD_ID_HELLO: u8 = 1;
-
-pub const HELLO_MESSAGE_MAX_LENGTH: usize = 512;
+// The wire format lives in the `claim-token-message` crate, so off-chain
+// tooling can decode messages exactly like this program does.
+pub use claim_token_message::{
+    MessageError, ALIVE_VERSION, HELLO_MESSAGE_MAX_LENGTH, MULTI_USER_INFO_CORRELATED_VERSION,
+    MULTI_USER_INFO_DELEGATED_VERSION, MULTI_USER_INFO_EXPIRING_VERSION,
+    MULTI_USER_INFO_MAX_CORRELATED_ENTRIES, MULTI_USER_INFO_MAX_DELEGATED_ENTRIES,
+    MULTI_USER_INFO_MAX_ENTRIES, MULTI_USER_INFO_MAX_EXPIRING_ENTRIES,
+    MULTI_USER_INFO_MAX_REFERRED_ENTRIES, MULTI_USER_INFO_REFERRED_VERSION,
+    MULTI_USER_INFO_VERSION,
+};
 
 #[derive(Clone)]
 /// Expected message types for this program. Only valid payloads are:
-/// * `Alive`: Payload ID == 0. Emitted when [`initialize`](crate::initialize)
-///  is called).
+/// * `Alive`: Payload ID == 0. Emitted when
+///   [`initialize_wormhole`](crate::initialize_wormhole) or
+///   [`send_message_alive`](crate::send_message_alive) is called. Encoded
+///   with an [`ALIVE_VERSION`] layout version unless `version` is 0.
 /// * `Hello`: Payload ID == 1. Emitted when
-/// [`send_message`](crate::send_message) is called).
+///   [`send_message`](crate::send_message) is called).
+/// * `MultiUserInfo`: Payload ID == 2. Funds the allocation of every user
+///   token account in `entries` when received. Amounts are expressed with
+///   `source_decimals` decimals and scaled to the mint's decimals. Encoded
+///   with a [`MULTI_USER_INFO_VERSION`] layout version, or
+///   [`MULTI_USER_INFO_DELEGATED_VERSION`] when `delegates` names the
+///   delegate of every entry. The default pubkey names no delegate. Encoded
+///   with [`MULTI_USER_INFO_EXPIRING_VERSION`] when `expiries` also holds the
+///   unix timestamp every allocation expires at. Zero sets no expiry. Encoded
+///   with [`MULTI_USER_INFO_CORRELATED_VERSION`] when `correlation_ids` also
+///   holds the id correlating every allocation with an off-chain request.
+///   All zeros sets no id. Encoded with [`MULTI_USER_INFO_REFERRED_VERSION`]
+///   when `referrers` also names the wallet that referred every user. The
+///   default pubkey names no referrer.
 ///
 /// Payload IDs are encoded as u8.
 pub enum HelloWorldMessage {
-    Alive { program_id: Pubkey },
-    Hello { message: Vec<u8> },
+    Alive {
+        version: u8,
+        program_id: Pubkey,
+    },
+    Hello {
+        message: Vec<u8>,
+    },
+    MultiUserInfo {
+        source_decimals: u8,
+        entries: Vec<(Pubkey, u64)>,
+        delegates: Vec<Pubkey>,
+        expiries: Vec<i64>,
+        correlation_ids: Vec<[u8; 16]>,
+        referrers: Vec<Pubkey>,
+    },
+}
+
+impl HelloWorldMessage {
+    /// Convenience method to check whether
+    /// [`receive_message`](crate::receive_message) accepts this message: a
+    /// `Hello` that fits the [Received](crate::state::Received) account, or a
+    /// `MultiUserInfo`.
+    pub fn is_receivable(&self) -> bool {
+        match self {
+            HelloWorldMessage::Hello { message } => message.len() <= HELLO_MESSAGE_MAX_LENGTH,
+            HelloWorldMessage::MultiUserInfo { .. } => true,
+            HelloWorldMessage::Alive { .. } => false,
+        }
+    }
+
+    /// Number of users funded by this message.
+    pub fn user_count(&self) -> usize {
+        match self {
+            HelloWorldMessage::MultiUserInfo { entries, .. } => entries.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Adapts an [`io::Read`] to the message decoder, keeping the first error
+/// other than running out of bytes so it can be returned as is.
+struct IoReader<'a, R> {
+    reader: &'a mut R,
+    error: Option<io::Error>,
+}
+
+impl<R: io::Read> Read for IoReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut filled = 0;
+        while filled < buf.len() && self.error.is_none() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(len) => filled += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => self.error = Some(err),
+            }
+        }
+        filled
+    }
 }
 
 impl AnchorSerialize for HelloWorldMessage {
     fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut encoded = Vec::new();
         match self {
-            HelloWorldMessage::Alive { program_id } => {
-                PAYLOAD_ID_ALIVE.serialize(writer)?;
-                program_id.serialize(writer)
-            }
+            HelloWorldMessage::Alive {
+                version,
+                program_id,
+            } => claim_token_message::encode_alive(*version, &program_id.to_bytes(), &mut encoded),
             HelloWorldMessage::Hello { message } => {
-                if message.len() > HELLO_MESSAGE_MAX_LENGTH {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        format!("message exceeds {HELLO_MESSAGE_MAX_LENGTH} bytes"),
-                    ))
-                } else {
-                    PAYLOAD_ID_HELLO.serialize(writer)?;
-                    (message.len() as u16).to_be_bytes().serialize(writer)?;
-                    for item in message {
-                        item.serialize(writer)?;
-                    }
-                    Ok(())
-                }
+                claim_token_message::encode_hello(message, &mut encoded)?
             }
+            HelloWorldMessage::MultiUserInfo {
+                source_decimals,
+                entries,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            } => Message::MultiUserInfo {
+                source_decimals: *source_decimals,
+                entries: entries
+                    .iter()
+                    .map(|(user, amount)| (user.to_bytes(), *amount))
+                    .collect(),
+                delegates: delegates
+                    .iter()
+                    .map(|delegate| delegate.to_bytes())
+                    .collect(),
+                expiries: expiries.clone(),
+                correlation_ids: correlation_ids.clone(),
+                referrers: referrers
+                    .iter()
+                    .map(|referrer| referrer.to_bytes())
+                    .collect(),
+            }
+            .encode(&mut encoded)?,
         }
+        writer.write_all(&encoded)
     }
 }
 
 impl AnchorDeserialize for HelloWorldMessage {
     fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
-        match u8::read(reader)? {
-            PAYLOAD_ID_ALIVE => Ok(HelloWorldMessage::Alive {
-                program_id: Pubkey::try_from(<[u8; 32]>::read(reader)?).unwrap(),
-            }),
-            PAYLOAD_ID_HELLO => {
-                let length = u16::read(reader)? as usize;
-                if length > HELLO_MESSAGE_MAX_LENGTH {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        format!("message exceeds {HELLO_MESSAGE_MAX_LENGTH} bytes"),
-                    ))
-                } else {
-                    let mut buf = vec![0; length];
-                    reader.read_exact(&mut buf)?;
-                    Ok(HelloWorldMessage::Hello { message: buf })
-                }
-            }
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "invalid payload ID",
-            )),
+        let mut reader = IoReader {
+            reader,
+            error: None,
+        };
+        // Trailing bytes are rejected, so a payload decodes to one message only.
+        let decoded = claim_token_message::decode_exact_from(&mut reader);
+        if let Some(err) = reader.error {
+            return Err(err);
         }
+        Ok(match decoded? {
+            Message::Alive {
+                version,
+                program_id,
+            } => HelloWorldMessage::Alive {
+                version,
+                program_id: Pubkey::new_from_array(program_id),
+            },
+            Message::Hello { message } => HelloWorldMessage::Hello { message },
+            Message::MultiUserInfo {
+                source_decimals,
+                entries,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            } => HelloWorldMessage::MultiUserInfo {
+                source_decimals,
+                entries: entries
+                    .into_iter()
+                    .map(|(user, amount)| (Pubkey::new_from_array(user), amount))
+                    .collect(),
+                delegates: delegates.into_iter().map(Pubkey::new_from_array).collect(),
+                expiries,
+                correlation_ids,
+                referrers: referrers.into_iter().map(Pubkey::new_from_array).collect(),
+            },
+        })
     }
 }
 
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::USER_AMOUNT_LEN;
     use anchor_lang::prelude::Result;
+    use claim_token_message::{PAYLOAD_ID_ALIVE, PAYLOAD_ID_HELLO, PAYLOAD_ID_MULTI_USER_INFO};
     use std::{mem::size_of, str, string::String};
 
+    fn assert_too_large(err: io::Error, len: usize, max: usize) {
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.get_ref()
+                .and_then(|err| err.downcast_ref::<MessageError>()),
+            Some(&MessageError::TooLarge { len, max })
+        );
+    }
+
     #[test]
     fn test_message_alive() -> Result<()> {
         let my_program_id = Pubkey::new_unique();
         let msg = HelloWorldMessage::Alive {
+            version: ALIVE_VERSION,
             program_id: my_program_id,
         };
 
@@ -90,7 +210,10 @@ pub mod test {
         let mut encoded = Vec::new();
         msg.serialize(&mut encoded)?;
 
-        assert_eq!(encoded.len(), size_of::<u8>() + size_of::<Pubkey>());
+        assert_eq!(
+            encoded.len(),
+            size_of::<u8>() + size_of::<Pubkey>() + size_of::<u8>() + size_of::<u16>()
+        );
 
         // Verify Payload ID.
         assert_eq!(encoded[0], PAYLOAD_ID_ALIVE);
@@ -100,9 +223,44 @@ pub mod test {
         program_id_bytes.copy_from_slice(&encoded[1..33]);
         assert_eq!(program_id_bytes, my_program_id.to_bytes());
 
+        // Verify version and the length of the appended fields.
+        assert_eq!(encoded[33], ALIVE_VERSION);
+        assert_eq!(encoded[34..], [0, 0]);
+
         // Now deserialize the encoded message.
         match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
-            HelloWorldMessage::Alive { program_id } => {
+            HelloWorldMessage::Alive {
+                version,
+                program_id,
+            } => {
+                assert_eq!(version, ALIVE_VERSION);
+                assert_eq!(program_id, my_program_id)
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_alive_legacy() -> Result<()> {
+        let my_program_id = Pubkey::new_unique();
+        let msg = HelloWorldMessage::Alive {
+            version: 0,
+            program_id: my_program_id,
+        };
+
+        // The legacy form ends at the program ID.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert_eq!(encoded.len(), size_of::<u8>() + size_of::<Pubkey>());
+
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::Alive {
+                version,
+                program_id,
+            } => {
+                assert_eq!(version, 0);
                 assert_eq!(program_id, my_program_id)
             }
             _ => assert!(false, "incorrect deserialization"),
@@ -111,6 +269,41 @@ pub mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_message_alive_future_version() -> Result<()> {
+        let my_program_id = Pubkey::new_unique();
+
+        // Fields appended by a newer version are skipped.
+        let mut encoded = vec![PAYLOAD_ID_ALIVE];
+        encoded.extend_from_slice(my_program_id.as_ref());
+        encoded.push(ALIVE_VERSION + 1);
+        encoded.extend_from_slice(&8u16.to_be_bytes());
+        encoded.extend_from_slice(&1_700_000_000i64.to_be_bytes());
+
+        let mut reader = encoded.as_slice();
+        match HelloWorldMessage::deserialize(&mut reader)? {
+            HelloWorldMessage::Alive {
+                version,
+                program_id,
+            } => {
+                assert_eq!(version, ALIVE_VERSION + 1);
+                assert_eq!(program_id, my_program_id)
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+        assert!(reader.is_empty(), "appended fields not consumed");
+
+        // But must be present in full.
+        assert_eq!(
+            HelloWorldMessage::deserialize(&mut &encoded[..encoded.len() - 1])
+                .err()
+                .map(|err| err.kind()),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_message_hello() -> Result<()> {
         let raw_message = String::from("All your base are belong to us");
@@ -171,7 +364,7 @@ pub mod test {
         // Attempt to serialize message above.
         let mut encoded = Vec::new();
         match msg.serialize(&mut encoded) {
-            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            Err(e) => assert_too_large(e, n, HELLO_MESSAGE_MAX_LENGTH),
             _ => assert!(false, "not supposed to serialize"),
         };
 
@@ -197,10 +390,539 @@ pub mod test {
         );
 
         match HelloWorldMessage::deserialize(&mut encoded.as_slice()) {
-            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            Err(e) => assert_too_large(e, n, HELLO_MESSAGE_MAX_LENGTH),
+            _ => assert!(false, "not supposed to deserialize"),
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_truncated() -> Result<()> {
+        // Headers declaring 40 bytes followed by only 10.
+        let mut hello = vec![PAYLOAD_ID_HELLO];
+        hello.extend_from_slice(&40u16.to_be_bytes());
+        let mut multi_user_info = vec![PAYLOAD_ID_MULTI_USER_INFO, MULTI_USER_INFO_VERSION, 8];
+        multi_user_info.extend_from_slice(&1u16.to_be_bytes());
+
+        for mut encoded in [hello, multi_user_info] {
+            encoded.extend_from_slice(&[1u8; 10]);
+            match HelloWorldMessage::deserialize(&mut encoded.as_slice()) {
+                Err(e) => {
+                    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                    assert_eq!(
+                        e.get_ref().and_then(|e| e.downcast_ref::<MessageError>()),
+                        Some(&MessageError::Truncated { len: 40 })
+                    );
+                }
+                _ => assert!(false, "not supposed to deserialize"),
+            };
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_multi_user_info() -> Result<()> {
+        let entries = vec![(Pubkey::new_unique(), 10_000), (Pubkey::new_unique(), 0)];
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        assert_eq!(msg.user_count(), 2);
+
+        // Serialize entries above.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+
+        assert_eq!(
+            encoded.len(),
+            size_of::<u8>()
+                + size_of::<u8>()
+                + size_of::<u8>()
+                + size_of::<u16>()
+                + entries.len() * USER_AMOUNT_LEN
+        );
+
+        // Verify Payload ID, version, decimals and entry count.
+        assert_eq!(encoded[0], PAYLOAD_ID_MULTI_USER_INFO);
+        assert_eq!(encoded[1], MULTI_USER_INFO_VERSION);
+        assert_eq!(encoded[2], 8);
+        assert_eq!(u16::from_be_bytes([encoded[3], encoded[4]]), 2);
+
+        // Verify first entry.
+        assert_eq!(encoded[5..37], entries[0].0.to_bytes());
+        assert_eq!(encoded[37..45], 10_000u64.to_be_bytes());
+
+        // Now deserialize the encoded message.
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::MultiUserInfo {
+                source_decimals,
+                entries: decoded,
+                delegates,
+                expiries,
+                correlation_ids,
+                referrers,
+            } => {
+                assert_eq!(source_decimals, 8);
+                assert_eq!(decoded, entries);
+                assert!(delegates.is_empty(), "delegates decoded");
+                assert!(expiries.is_empty(), "expiries decoded");
+                assert!(correlation_ids.is_empty(), "correlation ids decoded");
+                assert!(referrers.is_empty(), "referrers decoded");
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+
+        // The largest message fits in the maximum length.
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); MULTI_USER_INFO_MAX_ENTRIES],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_multi_user_info_delegated() -> Result<()> {
+        let entries = vec![(Pubkey::new_unique(), 10_000), (Pubkey::new_unique(), 0)];
+        let delegates = vec![Pubkey::new_unique(), Pubkey::default()];
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+
+        // Each entry is followed by its delegate.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert_eq!(
+            encoded.len(),
+            5 + entries.len() * (USER_AMOUNT_LEN + size_of::<Pubkey>())
+        );
+        assert_eq!(encoded[1], MULTI_USER_INFO_DELEGATED_VERSION);
+        assert_eq!(encoded[45..77], delegates[0].to_bytes());
+
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::MultiUserInfo {
+                entries: decoded,
+                delegates: decoded_delegates,
+                ..
+            } => {
+                assert_eq!(decoded, entries);
+                assert_eq!(decoded_delegates, delegates);
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+
+        // Fewer entries fit with delegates.
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); MULTI_USER_INFO_MAX_DELEGATED_ENTRIES + 1],
+            delegates: vec![Pubkey::new_unique(); MULTI_USER_INFO_MAX_DELEGATED_ENTRIES + 1],
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let mut encoded = Vec::new();
+        match msg.serialize(&mut encoded) {
+            Err(e) => assert_too_large(
+                e,
+                MULTI_USER_INFO_MAX_DELEGATED_ENTRIES + 1,
+                MULTI_USER_INFO_MAX_DELEGATED_ENTRIES,
+            ),
+            _ => assert!(false, "not supposed to serialize"),
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_multi_user_info_expiring() -> Result<()> {
+        let entries = vec![(Pubkey::new_unique(), 10_000), (Pubkey::new_unique(), 0)];
+        let delegates = vec![Pubkey::default(), Pubkey::new_unique()];
+        let expiries = vec![1_700_000_000, 0];
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: delegates.clone(),
+            expiries: expiries.clone(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+
+        // Each entry is followed by its delegate and expiry.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert_eq!(
+            encoded.len(),
+            5 + entries.len() * (USER_AMOUNT_LEN + size_of::<Pubkey>() + size_of::<i64>())
+        );
+        assert_eq!(encoded[1], MULTI_USER_INFO_EXPIRING_VERSION);
+        assert_eq!(encoded[77..85], 1_700_000_000i64.to_be_bytes());
+
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::MultiUserInfo {
+                entries: decoded,
+                delegates: decoded_delegates,
+                expiries: decoded_expiries,
+                ..
+            } => {
+                assert_eq!(decoded, entries);
+                assert_eq!(decoded_delegates, delegates);
+                assert_eq!(decoded_expiries, expiries);
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+
+        // The largest message fits in the maximum length.
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); MULTI_USER_INFO_MAX_EXPIRING_ENTRIES],
+            delegates: vec![Pubkey::new_unique(); MULTI_USER_INFO_MAX_EXPIRING_ENTRIES],
+            expiries: vec![1; MULTI_USER_INFO_MAX_EXPIRING_ENTRIES],
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_multi_user_info_correlated() -> Result<()> {
+        let entries = vec![(Pubkey::new_unique(), 10_000), (Pubkey::new_unique(), 0)];
+        let correlation_ids = vec![[4u8; 16], [0u8; 16]];
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: vec![Pubkey::default(); 2],
+            expiries: vec![0; 2],
+            correlation_ids: correlation_ids.clone(),
+            referrers: Vec::new(),
+        };
+
+        // Each entry is followed by its delegate, expiry and correlation id.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert_eq!(
+            encoded.len(),
+            5 + entries.len()
+                * (USER_AMOUNT_LEN
+                    + size_of::<Pubkey>()
+                    + size_of::<i64>()
+                    + size_of::<[u8; 16]>())
+        );
+        assert_eq!(encoded[1], MULTI_USER_INFO_CORRELATED_VERSION);
+        assert_eq!(encoded[85..101], correlation_ids[0]);
+
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::MultiUserInfo {
+                entries: decoded,
+                correlation_ids: decoded_correlation_ids,
+                ..
+            } => {
+                assert_eq!(decoded, entries);
+                assert_eq!(decoded_correlation_ids, correlation_ids);
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+
+        // The largest message fits in the maximum length.
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            delegates: vec![Pubkey::new_unique(); MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            expiries: vec![1; MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            correlation_ids: vec![[4u8; 16]; MULTI_USER_INFO_MAX_CORRELATED_ENTRIES],
+            referrers: Vec::new(),
+        };
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_multi_user_info_referred() -> Result<()> {
+        let entries = vec![(Pubkey::new_unique(), 10_000), (Pubkey::new_unique(), 0)];
+        let referrers = vec![Pubkey::new_unique(), Pubkey::default()];
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: entries.clone(),
+            delegates: vec![Pubkey::default(); 2],
+            expiries: vec![0; 2],
+            correlation_ids: vec![[0u8; 16]; 2],
+            referrers: referrers.clone(),
+        };
+
+        // Each entry is followed by its delegate, expiry, correlation id and
+        // referrer.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert_eq!(
+            encoded.len(),
+            5 + entries.len()
+                * (USER_AMOUNT_LEN
+                    + size_of::<Pubkey>()
+                    + size_of::<i64>()
+                    + size_of::<[u8; 16]>()
+                    + size_of::<Pubkey>())
+        );
+        assert_eq!(encoded[1], MULTI_USER_INFO_REFERRED_VERSION);
+        assert_eq!(encoded[101..133], referrers[0].to_bytes());
+
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::MultiUserInfo {
+                entries: decoded,
+                referrers: decoded_referrers,
+                ..
+            } => {
+                assert_eq!(decoded, entries);
+                assert_eq!(decoded_referrers, referrers);
+            }
+            _ => assert!(false, "incorrect deserialization"),
+        }
+
+        // The largest message fits in the maximum length.
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            delegates: vec![Pubkey::new_unique(); MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            expiries: vec![1; MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            correlation_ids: vec![[4u8; 16]; MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+            referrers: vec![Pubkey::new_unique(); MULTI_USER_INFO_MAX_REFERRED_ENTRIES],
+        };
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        assert!(encoded.len() <= HELLO_MESSAGE_MAX_LENGTH);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_multi_user_info_invalid() -> Result<()> {
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); MULTI_USER_INFO_MAX_ENTRIES + 1],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+
+        // Attempt to serialize message above.
+        let mut encoded = Vec::new();
+        match msg.serialize(&mut encoded) {
+            Err(e) => assert_too_large(
+                e,
+                MULTI_USER_INFO_MAX_ENTRIES + 1,
+                MULTI_USER_INFO_MAX_ENTRIES,
+            ),
+            _ => assert!(false, "not supposed to serialize"),
+        };
+
+        // Serialize manually and then attempt to deserialize.
+        encoded.push(PAYLOAD_ID_MULTI_USER_INFO);
+        encoded.push(MULTI_USER_INFO_VERSION);
+        encoded.push(8);
+        encoded.extend_from_slice(&((MULTI_USER_INFO_MAX_ENTRIES + 1) as u16).to_be_bytes());
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice()) {
+            Err(e) => assert_too_large(
+                e,
+                MULTI_USER_INFO_MAX_ENTRIES + 1,
+                MULTI_USER_INFO_MAX_ENTRIES,
+            ),
+            _ => assert!(false, "not supposed to deserialize"),
+        };
+
+        // A count promising more entries than the buffer holds is rejected.
+        let msg = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1); 2],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        encoded.truncate(encoded.len() - 1);
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice()) {
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                assert_eq!(
+                    e.get_ref().and_then(|e| e.downcast_ref::<MessageError>()),
+                    Some(&MessageError::Truncated {
+                        len: 2 * USER_AMOUNT_LEN
+                    })
+                );
+            }
+            _ => assert!(false, "not supposed to deserialize"),
+        };
+
+        // As is one without entries.
+        let empty = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: Vec::new(),
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        let mut encoded = Vec::new();
+        match empty.serialize(&mut encoded) {
+            Err(e) => assert_eq!(
+                e.get_ref().and_then(|e| e.downcast_ref::<MessageError>()),
+                Some(&MessageError::Empty)
+            ),
+            _ => assert!(false, "not supposed to serialize"),
+        };
+        encoded.extend_from_slice(&[PAYLOAD_ID_MULTI_USER_INFO, MULTI_USER_INFO_VERSION, 8, 0, 0]);
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice()) {
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                assert_eq!(
+                    e.get_ref().and_then(|e| e.downcast_ref::<MessageError>()),
+                    Some(&MessageError::Empty)
+                );
+            }
             _ => assert!(false, "not supposed to deserialize"),
         };
 
+        // Unknown layout versions are rejected.
+        let mut encoded = Vec::new();
+        msg.serialize(&mut encoded)?;
+        for version in [0, MULTI_USER_INFO_REFERRED_VERSION + 1] {
+            encoded[1] = version;
+            match HelloWorldMessage::deserialize(&mut encoded.as_slice()) {
+                Err(e) => {
+                    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                    assert_eq!(
+                        e.get_ref().and_then(|e| e.downcast_ref::<MessageError>()),
+                        Some(&MessageError::UnsupportedVersion { version })
+                    );
+                }
+                _ => assert!(false, "not supposed to deserialize"),
+            };
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_is_receivable() -> Result<()> {
+        let hello = |len| HelloWorldMessage::Hello {
+            message: vec![33u8; len],
+        };
+        assert!(hello(0).is_receivable(), "empty hello rejected");
+        assert!(
+            hello(HELLO_MESSAGE_MAX_LENGTH).is_receivable(),
+            "largest hello rejected"
+        );
+        assert!(
+            !hello(HELLO_MESSAGE_MAX_LENGTH + 1).is_receivable(),
+            "oversized hello accepted"
+        );
+
+        let multi_user_info = HelloWorldMessage::MultiUserInfo {
+            source_decimals: 8,
+            entries: vec![(Pubkey::new_unique(), 1)],
+            delegates: Vec::new(),
+            expiries: Vec::new(),
+            correlation_ids: Vec::new(),
+            referrers: Vec::new(),
+        };
+        assert!(multi_user_info.is_receivable(), "user info rejected");
+
+        // Heartbeats go through receive_heartbeat instead.
+        let alive = HelloWorldMessage::Alive {
+            version: ALIVE_VERSION,
+            program_id: Pubkey::new_unique(),
+        };
+        assert!(!alive.is_receivable(), "heartbeat accepted");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_trailing_bytes() -> Result<()> {
+        let messages = [
+            HelloWorldMessage::Hello {
+                message: b"All your base are belong to us".to_vec(),
+            },
+            HelloWorldMessage::MultiUserInfo {
+                source_decimals: 8,
+                entries: vec![(Pubkey::new_unique(), 10_000)],
+                delegates: Vec::new(),
+                expiries: Vec::new(),
+                correlation_ids: Vec::new(),
+                referrers: Vec::new(),
+            },
+        ];
+        for msg in messages {
+            let mut encoded = Vec::new();
+            msg.serialize(&mut encoded)?;
+            assert!(HelloWorldMessage::deserialize(&mut encoded.as_slice()).is_ok());
+
+            encoded.extend_from_slice(b"garbage");
+            let err = HelloWorldMessage::deserialize(&mut encoded.as_slice())
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.get_ref()
+                    .and_then(|err| err.downcast_ref::<MessageError>()),
+                Some(&MessageError::TrailingBytes)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_short_body() -> Result<()> {
+        // Each prefix of a valid message is missing bytes the decoder reads.
+        let mut alive = Vec::new();
+        HelloWorldMessage::Alive {
+            version: ALIVE_VERSION,
+            program_id: Pubkey::new_unique(),
+        }
+        .serialize(&mut alive)?;
+        // Any 33 bytes decode as a legacy Alive message, so stop short of it.
+        alive.truncate(size_of::<u8>() + size_of::<Pubkey>());
+        let mut hello = Vec::new();
+        HelloWorldMessage::Hello {
+            message: b"All your base are belong to us".to_vec(),
+        }
+        .serialize(&mut hello)?;
+
+        for encoded in [alive, hello] {
+            for len in 0..encoded.len() {
+                assert!(
+                    HelloWorldMessage::deserialize(&mut &encoded[..len]).is_err(),
+                    "{len} byte prefix deserialized"
+                );
+            }
+        }
+
         Ok(())
     }
 }
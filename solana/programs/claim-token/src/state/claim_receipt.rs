@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default)]
+/// Claim receipt account data.
+pub struct ClaimReceipt {
+    /// Whether the claim with this idempotency key has already transferred.
+    pub claimed: bool,
+    /// Amount transferred by that claim.
+    pub amount: u64,
+}
+
+impl ClaimReceipt {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 1 // claimed
+        + 8 // amount
+    ;
+    /// AKA `b"claim_receipt"`.
+    pub const SEED_PREFIX: &'static [u8; 13] = b"claim_receipt";
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_claim_receipt() -> Result<()> {
+        assert_eq!(
+            ClaimReceipt::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<bool>() + size_of::<u64>()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default)]
+/// Claimed Merkle leaf account data. Its existence marks the leaf of a user
+/// under a Merkle root as claimed.
+pub struct ClaimedLeaf {
+    /// Amount transferred by the claim.
+    pub amount: u64,
+}
+
+impl ClaimedLeaf {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 8 // amount
+    ;
+    /// AKA `b"claimed_leaf"`.
+    pub const SEED_PREFIX: &'static [u8; 12] = b"claimed_leaf";
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_claimed_leaf() -> Result<()> {
+        assert_eq!(
+            ClaimedLeaf::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<u64>()
+        );
+
+        Ok(())
+    }
+}
@@ -21,10 +21,9 @@ impl WormholeAddresses {
 
 #[account]
 #[derive(Default)]
-/// Config account data.
+/// Config account data. Created by
+/// [`initialize_wormhole`](crate::initialize_wormhole).
 pub struct Config {
-    /// Program's owner.
-    pub owner: Pubkey,
     /// Wormhole program's relevant addresses.
     pub wormhole: WormholeAddresses,
     /// AKA nonce. Just zero, but saving this information in this account
@@ -37,11 +36,9 @@ pub struct Config {
 
 impl Config {
     pub const MAXIMUM_SIZE: usize = 8 // discriminator
-        + 32 // owner
         + WormholeAddresses::LEN
         + 4 // batch_id
         + 1 // finality
-        
     ;
     /// AKA `b"config"`.
     pub const SEED_PREFIX: &'static [u8; 6] = b"config";
@@ -58,7 +55,6 @@ pub mod test {
         assert_eq!(
             Config::MAXIMUM_SIZE, 
             size_of::<u64>()
-            + size_of::<Pubkey>() 
             + size_of::<WormholeAddresses>()
             + size_of::<u32>()
             + size_of::<u8>()
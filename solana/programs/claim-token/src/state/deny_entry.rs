@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default)]
+/// Deny entry account data. Exists for every wallet the owner blocked from
+/// claiming.
+pub struct DenyEntry {
+    /// Blocked wallet.
+    pub wallet: Pubkey,
+}
+
+impl DenyEntry {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 32 // wallet
+    ;
+    /// AKA `b"deny"`.
+    pub const SEED_PREFIX: &'static [u8; 4] = b"deny";
+
+    /// Address of the deny entry account of `wallet`.
+    pub fn address(wallet: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, wallet.as_ref()], &crate::ID)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_deny_entry() -> Result<()> {
+        assert_eq!(
+            DenyEntry::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<Pubkey>()
+        );
+
+        // One account per wallet, derivable by anyone.
+        let wallet = Pubkey::new_unique();
+        let (address, _bump) = DenyEntry::address(&wallet);
+        assert_eq!(
+            Pubkey::find_program_address(&[b"deny", wallet.as_ref()], &crate::ID).0,
+            address
+        );
+        assert_ne!(address, DenyEntry::address(&Pubkey::new_unique()).0);
+
+        Ok(())
+    }
+}
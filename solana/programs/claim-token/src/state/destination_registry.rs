@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default)]
+/// Destination registry account data. Exists for every destination token
+/// account approved by the owner.
+pub struct DestinationRegistry {
+    /// Approved destination token account.
+    pub destination: Pubkey,
+}
+
+impl DestinationRegistry {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 32 // destination
+    ;
+    /// AKA `b"destination"`.
+    pub const SEED_PREFIX: &'static [u8; 11] = b"destination";
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_destination_registry() -> Result<()> {
+        assert_eq!(
+            DestinationRegistry::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<Pubkey>()
+        );
+
+        Ok(())
+    }
+}
@@ -6,23 +6,64 @@ use anchor_lang::prelude::*;
 pub struct ForeignEmitter {
     /// Emitter chain. Cannot equal `1` (Solana's Chain ID).
     pub chain: u16,
-    /// Emitter address. Cannot be zero address.
+    /// Emitter address. Cannot be zero address, and must be left-padded on
+    /// [EVM chains](Self::EVM_CHAINS).
     pub address: [u8; 32],
+    /// Sequence of the last message received from this emitter. Messages must
+    /// arrive with a strictly greater sequence. The first message from an
+    /// emitter is accepted at any sequence, as Wormhole sequences start at zero.
+    pub last_sequence: Option<u64>,
 }
 
 impl ForeignEmitter {
     pub const MAXIMUM_SIZE: usize = 8 // discriminator
         + 2 // chain
         + 32 // address
+        + 1 + 8 // last_sequence
     ;
     /// AKA `b"foreign_emitter"`.
     pub const SEED_PREFIX: &'static [u8; 15] = b"foreign_emitter";
+    /// Wormhole Chain IDs of EVM networks, whose emitter addresses are 20-byte
+    /// addresses left-padded to 32 bytes. Emitters on any other chain are
+    /// registered as raw 32-byte addresses.
+    pub const EVM_CHAINS: [u16; 15] = [
+        2,  // Ethereum
+        4,  // BSC
+        5,  // Polygon
+        6,  // Avalanche
+        7,  // Oasis
+        9,  // Aurora
+        10, // Fantom
+        11, // Karura
+        12, // Acala
+        13, // Klaytn
+        14, // Celo
+        16, // Moonbeam
+        23, // Arbitrum
+        24, // Optimism
+        30, // Base
+    ];
+
+    /// Convenience method to check whether `address` is well formed for
+    /// `chain`. EVM addresses must have their first 12 bytes zeroed.
+    pub fn is_valid_address(chain: u16, address: &[u8; 32]) -> bool {
+        !Self::EVM_CHAINS.contains(&chain) || address[..12].iter().all(|&x| x == 0)
+    }
 
     /// Convenience method to check whether an address equals the one saved in
     /// this account.
     pub fn verify(&self, address: &[u8; 32]) -> bool {
         *address == self.address
     }
+
+    /// Convenience method to check whether a sequence is newer than the last
+    /// one received from this emitter.
+    pub fn is_newer(&self, sequence: u64) -> bool {
+        match self.last_sequence {
+            Some(last_sequence) => sequence > last_sequence,
+            None => true,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -34,7 +75,11 @@ pub mod test {
     fn test_foreign_emitter() -> Result<()> {
         assert_eq!(
             ForeignEmitter::MAXIMUM_SIZE,
-            size_of::<u64>() + size_of::<u16>() + size_of::<[u8; 32]>()
+            size_of::<u64>()
+                + size_of::<u16>()
+                + size_of::<[u8; 32]>()
+                + size_of::<u8>()
+                + size_of::<u64>()
         );
 
         let chain = 2u16;
@@ -42,12 +87,75 @@ pub mod test {
             4u8, 20u8, 6u8, 9u8, 4u8, 20u8, 6u8, 9u8, 4u8, 20u8, 6u8, 9u8, 4u8, 20u8, 6u8, 9u8,
             4u8, 20u8, 6u8, 9u8, 4u8, 20u8, 6u8, 9u8, 4u8, 20u8, 6u8, 9u8, 4u8, 20u8, 6u8, 9u8,
         ];
-        let foreign_emitter = ForeignEmitter { chain, address };
+        let foreign_emitter = ForeignEmitter {
+            chain,
+            address,
+            ..Default::default()
+        };
         assert!(
             foreign_emitter.verify(&address),
             "foreign_emitter.verify(address) failed"
         );
 
+        let mut spoofed = address;
+        spoofed[31] ^= 1;
+        assert!(
+            !foreign_emitter.verify(&spoofed),
+            "foreign_emitter.verify(spoofed) succeeded"
+        );
+        assert!(
+            !foreign_emitter.verify(&[0u8; 32]),
+            "foreign_emitter.verify(zero address) succeeded"
+        );
+
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_foreign_emitter_address() -> Result<()> {
+        let mut padded = [0u8; 32];
+        padded[12..].copy_from_slice(&[0xab; 20]);
+        assert!(
+            ForeignEmitter::is_valid_address(2, &padded),
+            "padded EVM address rejected"
+        );
+
+        let mut malformed = padded;
+        malformed[11] = 1;
+        assert!(
+            !ForeignEmitter::is_valid_address(2, &malformed),
+            "malformed EVM address accepted"
+        );
+        assert!(
+            !ForeignEmitter::is_valid_address(30, &[1u8; 32]),
+            "raw address accepted on an EVM chain"
+        );
+
+        // Non-EVM chains accept any 32-byte address.
+        assert!(
+            ForeignEmitter::is_valid_address(3, &malformed),
+            "raw address rejected on a non-EVM chain"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_foreign_emitter_sequence() -> Result<()> {
+        let mut foreign_emitter = ForeignEmitter::default();
+        assert!(foreign_emitter.is_newer(0), "first sequence rejected");
+
+        for sequence in [1u64, 2] {
+            assert!(
+                foreign_emitter.is_newer(sequence),
+                "in-order sequence rejected"
+            );
+            foreign_emitter.last_sequence = Some(sequence);
+        }
+        assert!(!foreign_emitter.is_newer(2), "replayed sequence accepted");
+        assert!(!foreign_emitter.is_newer(1), "stale sequence accepted");
+        assert!(foreign_emitter.is_newer(5), "newer sequence rejected");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default)]
+/// Health state account data. Tracks the last heartbeat received from a
+/// foreign chain.
+pub struct HealthState {
+    /// Program ID carried by the last
+    /// [HelloWorldMessage::Alive](crate::message::HelloWorldMessage) message.
+    pub program_id: Pubkey,
+    /// Unix timestamp at which the last heartbeat was received.
+    pub last_seen: i64,
+}
+
+impl HealthState {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 32 // program_id
+        + 8 // last_seen
+    ;
+    /// AKA `b"health"`.
+    pub const SEED_PREFIX: &'static [u8; 6] = b"health";
+
+    /// Records a heartbeat from `program_id` received at unix timestamp `now`.
+    pub fn record(&mut self, program_id: Pubkey, now: i64) {
+        self.program_id = program_id;
+        self.last_seen = now;
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::message::{HelloWorldMessage, ALIVE_VERSION};
+    use std::mem::size_of;
+
+    #[test]
+    fn test_health_state() -> Result<()> {
+        assert_eq!(
+            HealthState::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<Pubkey>() + size_of::<i64>()
+        );
+
+        // Record the program ID of a round-tripped Alive message.
+        let foreign_program_id = Pubkey::new_unique();
+        let mut encoded = Vec::new();
+        HelloWorldMessage::Alive {
+            version: ALIVE_VERSION,
+            program_id: foreign_program_id,
+        }
+        .serialize(&mut encoded)?;
+
+        let mut health = HealthState::default();
+        match HelloWorldMessage::deserialize(&mut encoded.as_slice())? {
+            HelloWorldMessage::Alive { program_id, .. } => health.record(program_id, 1_700_000_000),
+            _ => assert!(false, "incorrect deserialization"),
+        }
+        assert_eq!(health.program_id, foreign_program_id);
+        assert_eq!(health.last_seen, 1_700_000_000);
+
+        Ok(())
+    }
+}
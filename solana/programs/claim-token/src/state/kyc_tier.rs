@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+
+use crate::CustomError;
+
+#[account]
+#[derive(Default)]
+/// KYC tier account data. Assigned by the owner to a claimer wallet.
+pub struct KycTier {
+    /// Tier assigned to the wallet.
+    pub tier: u8,
+    /// Maximum cumulative amount the wallet may claim.
+    pub tier_limit: u64,
+    /// Cumulative amount the wallet has claimed.
+    pub claimed: u64,
+}
+
+impl KycTier {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 1 // tier
+        + 8 // tier_limit
+        + 8 // claimed
+    ;
+    /// AKA `b"kyc"`.
+    pub const SEED_PREFIX: &'static [u8; 3] = b"kyc";
+
+    /// Adds `amount` to the cumulative claims, which must stay within
+    /// `tier_limit`.
+    pub fn record_claim(&mut self, amount: u64) -> Result<()> {
+        let claimed = self
+            .claimed
+            .checked_add(amount)
+            .filter(|claimed| *claimed <= self.tier_limit)
+            .ok_or(CustomError::KycLimitExceeded)?;
+        self.claimed = claimed;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_kyc_tier() -> Result<()> {
+        assert_eq!(
+            KycTier::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<u8>() + size_of::<u64>() + size_of::<u64>()
+        );
+
+        // Claims within the tier limit accumulate.
+        let mut basic = KycTier {
+            tier: 1,
+            tier_limit: 1_000,
+            claimed: 0,
+        };
+        basic.record_claim(600)?;
+        basic.record_claim(400)?;
+        assert_eq!(basic.claimed, 1_000);
+        assert_eq!(
+            basic.record_claim(1).unwrap_err(),
+            CustomError::KycLimitExceeded.into()
+        );
+        assert_eq!(basic.claimed, 1_000);
+
+        // A higher tier admits what the lower one rejected.
+        let mut full = KycTier {
+            tier: 2,
+            tier_limit: 100_000,
+            claimed: 1_000,
+        };
+        full.record_claim(99_000)?;
+        assert_eq!(
+            full.record_claim(u64::MAX).unwrap_err(),
+            CustomError::KycLimitExceeded.into()
+        );
+
+        Ok(())
+    }
+}
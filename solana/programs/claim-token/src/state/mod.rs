@@ -1,10 +1,26 @@
 
+pub use claim_receipt::*;
+pub use claimed_leaf::*;
 pub use config::*;
+pub use deny_entry::*;
+pub use destination_registry::*;
 pub use foreign_emitter::*;
+pub use health_state::*;
+pub use kyc_tier::*;
 pub use received::*;
+pub use relayer_stake::*;
+pub use user_state::*;
 pub use wormhole_emitter::*;
 
+pub mod claim_receipt;
+pub mod claimed_leaf;
 pub mod config;
+pub mod deny_entry;
+pub mod destination_registry;
 pub mod foreign_emitter;
+pub mod health_state;
+pub mod kyc_tier;
 pub mod received;
+pub mod relayer_stake;
+pub mod user_state;
 pub mod wormhole_emitter;
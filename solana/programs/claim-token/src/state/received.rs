@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-pub const MESSAGE_MAX_LENGTH: usize = 1024;
+use crate::message::HELLO_MESSAGE_MAX_LENGTH;
 
 #[account]
 #[derive(Default)]
@@ -11,6 +11,7 @@ pub struct Received {
     /// Keccak256 hash of verified Wormhole message.
     pub wormhole_message_hash: [u8; 32],
     /// HelloWorldMessage from [HelloWorldMessage::Hello](crate::message::HelloWorldMessage).
+    /// Empty for other payloads.
     pub message: Vec<u8>,
 }
 
@@ -19,10 +20,16 @@ impl Received {
         + 4 // batch_id
         + 32 // wormhole_message_hash
         + 4 // Vec length
-        + MESSAGE_MAX_LENGTH // message
+        + HELLO_MESSAGE_MAX_LENGTH // message
     ;
     /// AKA `b"received"`.
     pub const SEED_PREFIX: &'static [u8; 8] = b"received";
+
+    /// Size to grow an account of `data_len` bytes to, or `None` if it already
+    /// fits the current layout. Accounts never shrink.
+    pub fn migrated_len(data_len: usize) -> Option<usize> {
+        (data_len < Self::MAXIMUM_SIZE).then_some(Self::MAXIMUM_SIZE)
+    }
 }
 
 #[cfg(test)]
@@ -38,9 +45,53 @@ pub mod test {
                 + size_of::<u32>()
                 + size_of::<[u8; 32]>()
                 + size_of::<u32>()
-                + MESSAGE_MAX_LENGTH
+                + HELLO_MESSAGE_MAX_LENGTH
+        );
+
+        // The largest message accepted by deserialization fills the account
+        // exactly.
+        let received = Received {
+            message: vec![33u8; HELLO_MESSAGE_MAX_LENGTH],
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        received.try_serialize(&mut data)?;
+        assert_eq!(data.len(), Received::MAXIMUM_SIZE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_received_migration() -> Result<()> {
+        let received = Received {
+            batch_id: 7,
+            wormhole_message_hash: [1u8; 32],
+            message: b"All your base are belong to us".to_vec(),
+        };
+        let mut data = Vec::new();
+        received.try_serialize(&mut data)?;
+
+        // An account sized for an older, shorter layout.
+        let old_len = data.len();
+        assert_eq!(
+            Received::migrated_len(old_len),
+            Some(Received::MAXIMUM_SIZE)
+        );
+
+        // Growing zeroes the tail, leaving prior fields intact.
+        data.resize(Received::migrated_len(old_len).unwrap(), 0);
+        let migrated = Received::try_deserialize(&mut data.as_slice())?;
+        assert_eq!(migrated.batch_id, received.batch_id);
+        assert_eq!(
+            migrated.wormhole_message_hash,
+            received.wormhole_message_hash
         );
+        assert_eq!(migrated.message, received.message);
+
+        // Accounts at the current size or larger are never shrunk.
+        assert_eq!(Received::migrated_len(Received::MAXIMUM_SIZE), None);
+        assert_eq!(Received::migrated_len(Received::MAXIMUM_SIZE + 1), None);
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(Default)]
+/// Relayer stake account data.
+pub struct RelayerStake {
+    /// Relayer that deposited the stake.
+    pub relayer: Pubkey,
+    /// Staked lamports, excluding the rent-exempt reserve of this account.
+    pub amount: u64,
+}
+
+impl RelayerStake {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 32 // relayer
+        + 8 // amount
+    ;
+    /// AKA `b"relayer_stake"`.
+    pub const SEED_PREFIX: &'static [u8; 13] = b"relayer_stake";
+
+    /// Convenience method to check whether the staked amount satisfies the
+    /// minimum configured in the [State](crate::State).
+    pub fn is_sufficient(&self, min_relayer_stake: u64) -> bool {
+        self.amount >= min_relayer_stake
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_relayer_stake() -> Result<()> {
+        assert_eq!(
+            RelayerStake::MAXIMUM_SIZE,
+            size_of::<u64>() + size_of::<Pubkey>() + size_of::<u64>()
+        );
+
+        let stake = RelayerStake {
+            relayer: Pubkey::new_unique(),
+            amount: 1_000,
+        };
+        assert!(stake.is_sufficient(0), "stake.is_sufficient(0) failed");
+        assert!(
+            stake.is_sufficient(1_000),
+            "stake.is_sufficient(1_000) failed"
+        );
+        assert!(!stake.is_sufficient(1_001), "unstaked relayer accepted");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use anchor_lang::prelude::*;
+
+use crate::{normalize_amount, CustomError};
+
+#[account]
+#[derive(Default)]
+/// User state account data. Holds the allocation of one destination token
+/// account, credited by [`receive_message`](crate::receive_message).
+pub struct UserState {
+    /// Destination token account the allocation is keyed by.
+    pub user: Pubkey,
+    /// Allocation left to claim, in the mint's decimals.
+    pub amount: u64,
+    /// Sum of all claims drawn from the allocation.
+    pub claimed: u64,
+    /// Fractions of a unit, in [`UserState::DUST_PRECISION`] parts, truncated
+    /// when scaling credits down to the mint's decimals. Paid out to `amount`
+    /// once they add up to a whole unit.
+    pub dust: u64,
+    /// Wallet allowed to sign claims of the allocation besides the owner of
+    /// `user`, named by the last message crediting it with a delegate.
+    pub delegate: Option<Pubkey>,
+    /// Mint the allocation is denominated in, set when it is first credited.
+    /// Claims must be paid in it.
+    pub mint: Pubkey,
+    /// Unix timestamp after which the allocation can no longer be claimed,
+    /// set by the last message crediting it with one. Zero never expires.
+    pub expiry: i64,
+    /// Basis points taken off the protocol fee of the allocation's claims, set
+    /// by the owner.
+    pub fee_discount_bps: u16,
+    /// Whether the allocation was injected by the owner for testing, see
+    /// [`inject_test_allocation`](crate::inject_test_allocation).
+    pub is_test: bool,
+    /// Whether the allocation was credited above the state's
+    /// `review_threshold` and awaits
+    /// [`approve_claim`](crate::approve_claim). Claims fail until then.
+    pub pending_review: bool,
+    /// Token account claims of the allocation are paid into instead of
+    /// `user`, set by the wallet owning `user`. Zero pays `user`.
+    pub recipient: Pubkey,
+    /// Id correlating the allocation with an off-chain request, set by the
+    /// last message crediting it with one. Reported by its claims'
+    /// [`TokenClaimed`](crate::TokenClaimed) events.
+    pub correlation_id: [u8; 16],
+    /// Wallet that referred the user, set by the first message crediting the
+    /// allocation with one. Paid the state's `referral_bps` of its claims.
+    /// Zero names no referrer.
+    pub referrer: Pubkey,
+}
+
+impl UserState {
+    pub const MAXIMUM_SIZE: usize = 8 // discriminator
+        + 32 // user
+        + 8 // amount
+        + 8 // claimed
+        + 8 // dust
+        + 1 + 32 // delegate
+        + 32 // mint
+        + 8 // expiry
+        + 2 // fee_discount_bps
+        + 1 // is_test
+        + 1 // pending_review
+        + 32 // recipient
+        + 16 // correlation_id
+        + 32 // referrer
+    ;
+    /// AKA `b"user"`.
+    pub const SEED_PREFIX: &'static [u8; 4] = b"user";
+
+    /// Parts of a unit tracked in `dust`.
+    pub const DUST_PRECISION: u64 = 1_000_000_000_000_000_000;
+
+    /// Address of the user state account of `user`.
+    pub fn address(user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[Self::SEED_PREFIX, user.as_ref()], &crate::ID)
+    }
+
+    /// Convenience method to check whether tokens were allocated and all of
+    /// them claimed.
+    pub fn is_claimed(&self) -> bool {
+        self.amount == 0 && self.claimed > 0
+    }
+
+    /// Convenience method to check whether `key` is the allocation's
+    /// delegate.
+    pub fn is_delegate(&self, key: &Pubkey) -> bool {
+        self.delegate == Some(*key)
+    }
+
+    /// Token account claims of the allocation are paid into: `recipient` if
+    /// set, otherwise `user`.
+    pub fn destination(&self) -> Pubkey {
+        if self.recipient == Pubkey::default() {
+            self.user
+        } else {
+            self.recipient
+        }
+    }
+
+    /// Referrer paid a cut of claims signed for `owner`, the wallet owning
+    /// `user`. Allocations without a referrer, or referred by their own
+    /// owner or token account, pay none.
+    pub fn referrer_of(&self, owner: &Pubkey) -> Option<Pubkey> {
+        Some(self.referrer)
+            .filter(|referrer| ![Pubkey::default(), self.user, *owner].contains(referrer))
+    }
+
+    /// Convenience method to check whether the allocation's own expiry has
+    /// passed at `now`.
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry != 0 && now > self.expiry
+    }
+
+    /// Credits `amount` expressed with `from_decimals` decimals, scaled to
+    /// `to_decimals`. When scaling down, the truncated remainder is kept in
+    /// `dust`. Returns the whole units credited. Nothing changes on error.
+    pub fn credit(&mut self, amount: u64, from_decimals: u8, to_decimals: u8) -> Result<u64> {
+        let mut credited = normalize_amount(amount, from_decimals, to_decimals)?;
+        let mut dust = self.dust;
+
+        if from_decimals > to_decimals {
+            // Remainders below a part of DUST_PRECISION are dropped.
+            let fraction = 10u128
+                .checked_pow((from_decimals - to_decimals).into())
+                .map_or(0, |scale| {
+                    u128::from(amount) % scale * u128::from(Self::DUST_PRECISION) / scale
+                });
+
+            // Both parts are below DUST_PRECISION, so at most one unit is
+            // paid out.
+            dust += fraction as u64;
+            if dust >= Self::DUST_PRECISION {
+                dust -= Self::DUST_PRECISION;
+                credited = credited.checked_add(1).ok_or(CustomError::AmountOverflow)?;
+            }
+        }
+
+        self.amount = self
+            .amount
+            .checked_add(credited)
+            .ok_or(CustomError::AmountOverflow)?;
+        self.dust = dust;
+        Ok(credited)
+    }
+
+    /// Deducts `amount` from the allocation. The amount must be nonzero and no
+    /// larger than what remains. A user that already withdrew everything gets
+    /// `AlreadyClaimed` rather than `Unauthorized`.
+    pub fn debit(&mut self, amount: u64) -> Result<()> {
+        require!(
+            self.amount > 0 || self.claimed > 0,
+            CustomError::Unauthorized
+        );
+        require!(self.amount > 0, CustomError::AlreadyClaimed);
+        require!(
+            amount > 0 && amount <= self.amount,
+            CustomError::InvalidAmount
+        );
+        self.amount -= amount;
+        self.claimed = self.claimed.saturating_add(amount);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_user_state() -> Result<()> {
+        assert_eq!(
+            UserState::MAXIMUM_SIZE,
+            size_of::<u64>()
+                + size_of::<Pubkey>()
+                + size_of::<u64>()
+                + size_of::<u64>()
+                + size_of::<u64>()
+                + size_of::<u8>()
+                + size_of::<Pubkey>()
+                + size_of::<Pubkey>()
+                + size_of::<i64>()
+                + size_of::<u16>()
+                + size_of::<bool>()
+                + size_of::<bool>()
+                + size_of::<Pubkey>()
+                + size_of::<[u8; 16]>()
+                + size_of::<Pubkey>()
+        );
+        assert!(
+            UserState::default().delegate.is_none(),
+            "user state starts with a delegate"
+        );
+
+        // One account per user, derivable by anyone.
+        let user = Pubkey::new_unique();
+        let (address, _bump) = UserState::address(&user);
+        assert_eq!(
+            Pubkey::find_program_address(&[b"user", user.as_ref()], &crate::ID).0,
+            address
+        );
+        assert_ne!(address, UserState::address(&Pubkey::new_unique()).0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_delegate() -> Result<()> {
+        let delegate = Pubkey::new_unique();
+        let mut user_state = UserState::default();
+        assert!(!user_state.is_delegate(&delegate), "no delegate matched");
+        assert!(
+            !user_state.is_delegate(&Pubkey::default()),
+            "default pubkey matched"
+        );
+
+        user_state.delegate = Some(delegate);
+        assert!(user_state.is_delegate(&delegate));
+        assert!(
+            !user_state.is_delegate(&Pubkey::new_unique()),
+            "other wallet matched"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_expired() -> Result<()> {
+        let mut user_state = UserState::default();
+        assert!(!user_state.is_expired(i64::MAX), "no expiry expired");
+
+        // Claimable up to and including the expiry itself.
+        user_state.expiry = 1_700_000_000;
+        assert!(!user_state.is_expired(1_700_000_000));
+        assert!(user_state.is_expired(1_700_000_001));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_destination() -> Result<()> {
+        let mut user_state = UserState {
+            user: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        assert_eq!(user_state.destination(), user_state.user);
+
+        user_state.recipient = Pubkey::new_unique();
+        assert_eq!(user_state.destination(), user_state.recipient);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_referrer_of() -> Result<()> {
+        let owner = Pubkey::new_unique();
+        let mut user_state = UserState {
+            user: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        assert_eq!(user_state.referrer_of(&owner), None);
+
+        user_state.referrer = Pubkey::new_unique();
+        assert_eq!(user_state.referrer_of(&owner), Some(user_state.referrer));
+
+        // Self-referrals pay nothing.
+        user_state.referrer = owner;
+        assert_eq!(user_state.referrer_of(&owner), None);
+        user_state.referrer = user_state.user;
+        assert_eq!(user_state.referrer_of(&owner), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_credit() -> Result<()> {
+        // Equal decimals and scaling up credit the whole amount.
+        let mut user_state = UserState::default();
+        assert_eq!(user_state.credit(10_000, 6, 6)?, 10_000);
+        assert_eq!(user_state.credit(1, 6, 9)?, 1_000);
+        assert_eq!(user_state.amount, 11_000);
+        assert_eq!(user_state.dust, 0);
+
+        // 8 -> 6: each credit of 40 leaves 0.4 units of dust, paid out on the
+        // third credit.
+        let mut user_state = UserState::default();
+        assert_eq!(user_state.credit(40, 8, 6)?, 0);
+        assert_eq!(user_state.dust, UserState::DUST_PRECISION / 10 * 4);
+        assert_eq!(user_state.credit(40, 8, 6)?, 0);
+        assert_eq!(user_state.amount, 0);
+        assert_eq!(user_state.dust, UserState::DUST_PRECISION / 10 * 8);
+        assert_eq!(user_state.credit(40, 8, 6)?, 1);
+        assert_eq!(user_state.amount, 1);
+        assert_eq!(user_state.dust, UserState::DUST_PRECISION / 10 * 2);
+
+        // Dust adding up to exactly a unit leaves nothing behind.
+        let mut user_state = UserState::default();
+        user_state.credit(150, 8, 6)?;
+        user_state.credit(50, 8, 6)?;
+        assert_eq!(user_state.amount, 2);
+        assert_eq!(user_state.dust, 0);
+
+        // Allocations never wrap around, whether the credit itself or the
+        // dust paid out with it overflows.
+        let mut user_state = UserState::default();
+        user_state.credit(u64::MAX, 6, 6)?;
+        assert_eq!(
+            user_state.credit(1, 6, 6).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+        assert_eq!(user_state.amount, u64::MAX);
+
+        let mut user_state = UserState::default();
+        user_state.credit(u64::MAX, 6, 6)?;
+        user_state.credit(50, 8, 6)?;
+        assert_eq!(
+            user_state.credit(50, 8, 6).unwrap_err(),
+            CustomError::AmountOverflow.into()
+        );
+        assert_eq!(user_state.amount, u64::MAX);
+        assert_eq!(user_state.dust, UserState::DUST_PRECISION / 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_debit() -> Result<()> {
+        let mut user_state = UserState {
+            amount: 10_000,
+            ..Default::default()
+        };
+
+        // Partial claims draw the allocation down.
+        user_state.debit(4_000)?;
+        assert_eq!(user_state.amount, 6_000);
+        user_state.debit(6_000)?;
+        assert_eq!(user_state.amount, 0);
+        assert_eq!(user_state.claimed, 10_000);
+        assert!(user_state.is_claimed(), "drained allocation not claimed");
+
+        // Over-limit and zero claims are rejected without touching the balance.
+        user_state.amount = 100;
+        assert_eq!(
+            user_state.debit(101).unwrap_err(),
+            CustomError::InvalidAmount.into()
+        );
+        assert_eq!(
+            user_state.debit(0).unwrap_err(),
+            CustomError::InvalidAmount.into()
+        );
+        assert_eq!(user_state.amount, 100);
+
+        // A drained allocation is reported as claimed, one that was only ever
+        // credited dust as never allocated.
+        user_state.debit(100)?;
+        assert_eq!(
+            user_state.debit(1).unwrap_err(),
+            CustomError::AlreadyClaimed.into()
+        );
+        let mut user_state = UserState {
+            dust: 1,
+            ..Default::default()
+        };
+        assert!(!user_state.is_claimed(), "unallocated user claimed");
+        assert_eq!(
+            user_state.debit(1).unwrap_err(),
+            CustomError::Unauthorized.into()
+        );
+
+        Ok(())
+    }
+}